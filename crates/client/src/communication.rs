@@ -1,14 +1,22 @@
 use bevy::prelude::*;
 
 use bevy_http_client::*;
-use serde::Serialize;
-use serde::Deserialize;
+use protocol::{
+    auth::{LoginData, SessionData},
+    error::ErrorBody,
+    routes,
+};
+
+/// Host and port of the game server.
+pub const SERVER: &str = "127.0.0.1:8000";
+
 pub struct ComPlugin;
 
 impl Plugin for ComPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(HttpClientPlugin);
         app.init_resource::<ApiTimer>();
+        app.init_resource::<AuthToken>();
 
         app.add_systems(Update, (send_login, handle_login));
     }
@@ -23,28 +31,41 @@ impl Default for ApiTimer {
     }
 }
 
-#[derive(Deserialize, Serialize)]
-pub struct LoginData {
-    pub name: String,
-    pub password: String,
-}
+/// Session token received on login, sent back since we have no cookie jar.
+#[derive(Resource, Default)]
+pub struct AuthToken(pub Option<String>);
+
+/// Marks the request entity of a login, so other responses aren't taken for one.
+#[derive(Component)]
+pub struct LoginRequest;
 
 fn send_login(mut commands: Commands, time: Res<Time>, mut timer: ResMut<ApiTimer>) {
     timer.tick(time.delta());
 
     if timer.just_finished() {
         let data = serde_json::to_vec(&LoginData{name: "Test".to_string(), password: "test".to_string()}).unwrap();
-        let mut req = ehttp::Request::post("http://127.0.0.1:8000/users/login", data);
+        let mut req = ehttp::Request::post(format!("http://{}{}", SERVER, routes::LOGIN), data);
         req
                 .headers
                 .insert("Content-Type".into(), "application/json".into());
-        commands.spawn(HttpRequest(dbg!(req)));
+        commands.spawn((HttpRequest(dbg!(req)), LoginRequest));
     }
 }
 
-fn handle_login(mut commands: Commands, responses: Query<(Entity, &HttpResponse)>) {
+fn handle_login(
+    mut commands: Commands,
+    responses: Query<(Entity, &HttpResponse), With<LoginRequest>>,
+    mut token: ResMut<AuthToken>,
+) {
     for (entity, response) in responses.iter() {
         info!("response: {:?}", response.headers);
+        match serde_json::from_slice::<SessionData>(&response.bytes) {
+            Ok(session) => token.0 = Some(session.token),
+            Err(err) => match serde_json::from_slice::<ErrorBody>(&response.bytes) {
+                Ok(error) => warn!("login failed: {:?} {}", error.code, error.message),
+                Err(_) => warn!("login failed: {} {}", response.status, err),
+            },
+        }
         commands.entity(entity).despawn_recursive();
     }
 }
@@ -0,0 +1,119 @@
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use protocol::{leaderboard::Entry, live::GameEvent, points::GLOBAL_POOL, routes};
+
+use crate::communication::{AuthToken, SERVER};
+use crate::{ChampionId, Points, SharedPool};
+
+/// Keeps champions, the shared pool and leaderboards in sync with the server's `/ws` push.
+pub struct LivePlugin;
+
+impl Plugin for LivePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_non_send_resource::<LiveConnection>();
+        app.init_resource::<ReconnectTimer>();
+        app.init_resource::<Leaderboards>();
+
+        app.add_systems(Update, (connect_live, receive_live).chain());
+    }
+}
+
+/// Open socket, if any; `ewebsock` handles aren't `Send` on every platform.
+#[derive(Default)]
+pub struct LiveConnection(Option<(ewebsock::WsSender, ewebsock::WsReceiver)>);
+
+#[derive(Resource, Deref, DerefMut)]
+pub struct ReconnectTimer(pub Timer);
+
+impl Default for ReconnectTimer {
+    fn default() -> Self {
+        Self(Timer::from_seconds(3.0, TimerMode::Once))
+    }
+}
+
+/// Top of each leaderboard, by board name, as last pushed by the server.
+#[derive(Resource, Default)]
+pub struct Leaderboards(pub HashMap<String, Vec<Entry>>);
+
+/// Opens the socket once logged in, and again a bit after it closes.
+fn connect_live(
+    mut connection: NonSendMut<LiveConnection>,
+    token: Res<AuthToken>,
+    time: Res<Time>,
+    mut timer: ResMut<ReconnectTimer>,
+) {
+    let Some(token) = &token.0 else {
+        return;
+    };
+    if connection.0.is_some() {
+        return;
+    }
+    timer.tick(time.delta());
+    if !timer.finished() {
+        return;
+    }
+    timer.reset();
+
+    let url = format!("ws://{}{}?token={}", SERVER, routes::WS, token);
+    match ewebsock::connect(url, ewebsock::Options::default()) {
+        Ok(socket) => connection.0 = Some(socket),
+        Err(err) => warn!("live updates failed to connect: {}", err),
+    }
+}
+
+fn receive_live(
+    mut connection: NonSendMut<LiveConnection>,
+    mut champions: Query<(&ChampionId, &mut Points), Without<SharedPool>>,
+    mut pools: Query<&mut Points, With<SharedPool>>,
+    mut leaderboards: ResMut<Leaderboards>,
+) {
+    let Some((_, receiver)) = &connection.0 else {
+        return;
+    };
+    let mut closed = false;
+    while let Some(event) = receiver.try_recv() {
+        let text = match event {
+            ewebsock::WsEvent::Message(ewebsock::WsMessage::Text(text)) => text,
+            ewebsock::WsEvent::Message(_) => continue,
+            ewebsock::WsEvent::Opened => {
+                info!("live updates connected");
+                continue;
+            }
+            ewebsock::WsEvent::Error(err) => {
+                warn!("live updates error: {}", err);
+                closed = true;
+                break;
+            }
+            ewebsock::WsEvent::Closed => {
+                closed = true;
+                break;
+            }
+        };
+        match serde_json::from_str::<GameEvent>(&text) {
+            Ok(GameEvent::ChampionPoints {
+                champion_id,
+                points,
+            }) => {
+                for (id, mut champion_points) in champions.iter_mut() {
+                    if id.0 == champion_id {
+                        champion_points.0 = points;
+                    }
+                }
+            }
+            // Only the global pool is shown for now.
+            Ok(GameEvent::Pool(pool)) if pool.name == GLOBAL_POOL => {
+                for mut pool_points in pools.iter_mut() {
+                    pool_points.0 = pool.points;
+                }
+            }
+            Ok(GameEvent::Pool(_)) => {}
+            Ok(GameEvent::Leaderboard { board, entries }) => {
+                leaderboards.0.insert(board, entries);
+            }
+            Err(err) => warn!("unknown live update: {} {}", err, text),
+        }
+    }
+    if closed {
+        connection.0 = None;
+    }
+}
@@ -1,4 +1,5 @@
 mod communication;
+mod live;
 
 use bevy::math::{vec3, vec4};
 use bevy::{
@@ -11,6 +12,7 @@ use bevy_mod_picking::prelude::*;
 use std::f32::consts::PI;
 use std::time::Duration;
 use crate::communication::ComPlugin;
+use crate::live::LivePlugin;
 
 fn main() {
     App::new()
@@ -18,7 +20,8 @@ fn main() {
             DefaultPlugins.set(low_latency_window_plugin()),
             DefaultPickingPlugins.build(), //.disable::<DebugPickingPlugin>(),
             NoCameraPlayerPlugin,
-            ComPlugin
+            ComPlugin,
+            LivePlugin,
         ))
         .insert_resource(MovementSettings {
             sensitivity: 0.0001, // default: 0.00012
@@ -90,12 +93,14 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>, mut meshes: Res
         &mut commands,
         &models,
         &mut meshes,
+        ChampionId(1),
         Transform::from_translation(Vec3::new(-0.5f32, 0f32, 0f32)),
     );
     create_mouse(
         &mut commands,
         &models,
         &mut meshes,
+        ChampionId(2),
         Transform::from_translation(Vec3::new(0.5f32, 0f32, 0f32)),
     );
 
@@ -157,11 +162,13 @@ fn create_mouse(
     commands: &mut Commands<'_, '_>,
     models: &Models,
     meshes: &mut ResMut<'_, Assets<Mesh>>,
+    id: ChampionId,
     transform: Transform,
 ) {
     commands
         .spawn((
             Champion,
+            id,
             SpatialBundle {
                 transform,
                 ..default()
@@ -266,6 +273,10 @@ pub struct SharedPool;
 #[derive(Component)]
 pub struct Champion;
 
+/// Server id of a champion, to match the live updates.
+#[derive(Component, Clone, Copy, PartialEq, Eq)]
+pub struct ChampionId(pub i32);
+
 #[derive(Component)]
 pub struct Cooldown {
     pub ready_at: Instant,
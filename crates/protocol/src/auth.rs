@@ -0,0 +1,71 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+#[derive(Serialize, Deserialize)]
+pub struct SignupData {
+    pub name: String,
+    pub password: String,
+    /// Checked against `password` when given, for forms asking to type it twice.
+    #[serde(default)]
+    pub password_confirm: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LoginData {
+    pub name: String,
+    pub password: String,
+}
+
+/// Body of a successful signup or login, for clients without a cookie jar.
+///
+/// The token goes in an `Authorization: Bearer <token>` header.
+#[derive(Serialize, Deserialize)]
+pub struct SessionData {
+    pub token: String,
+    /// Seconds until the session expires if it is not renewed.
+    pub expires_in: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SessionInfo {
+    pub id: i32,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub last_seen_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub expires_at: OffsetDateTime,
+    /// Whether this is the session making the request.
+    pub current: bool,
+}
+
+/// What a user is allowed to do; each role can do everything the ones before it can.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type))]
+#[cfg_attr(
+    feature = "sqlx",
+    sqlx(type_name = "user_role", rename_all = "lowercase")
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Player,
+    Moderator,
+    Admin,
+}
+
+impl Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Role::Player => f.write_str("player"),
+            Role::Moderator => f.write_str("moderator"),
+            Role::Admin => f.write_str("admin"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RoleData {
+    pub role: Role,
+}
@@ -0,0 +1,114 @@
+use serde::{Deserialize, Serialize};
+
+/// Stable, machine-readable identifier of an error, for clients to switch on.
+///
+/// Serialized in `snake_case`; never rename a variant once clients rely on it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The body, path or query could not be read, `message` says why.
+    InvalidRequest,
+    MissingDetails,
+    InvalidName,
+    NameExists,
+    PasswordsDoNotMatch,
+    InvalidPassword,
+    InvalidCredentials,
+    WrongPassword,
+    InvalidResetCode,
+    InvalidAmount,
+    InvalidPoolSettings,
+    TooManyAttempts,
+    Unauthorized,
+    Forbidden,
+    UserNotFound,
+    SessionNotFound,
+    ChampionNotFound,
+    TeamNotFound,
+    PoolNotFound,
+    LeaderboardNotFound,
+    WrongTeam,
+    CollectCooldown,
+    TeamCooldown,
+    PoolUnavailable,
+    InsufficientPoints,
+    /// The body is over the size limit of the server.
+    PayloadTooLarge,
+    InternalError,
+}
+
+impl ErrorCode {
+    /// HTTP status of the responses carrying this code.
+    pub fn status(self) -> u16 {
+        match self {
+            ErrorCode::InvalidRequest
+            | ErrorCode::MissingDetails
+            | ErrorCode::InvalidName
+            | ErrorCode::PasswordsDoNotMatch
+            | ErrorCode::InvalidPassword
+            | ErrorCode::InvalidResetCode
+            | ErrorCode::InvalidAmount
+            | ErrorCode::InvalidPoolSettings => 400,
+            ErrorCode::InvalidCredentials | ErrorCode::Unauthorized => 401,
+            // The user is logged in, a 401 would make clients drop their session.
+            ErrorCode::WrongPassword | ErrorCode::Forbidden | ErrorCode::WrongTeam => 403,
+            ErrorCode::UserNotFound
+            | ErrorCode::SessionNotFound
+            | ErrorCode::ChampionNotFound
+            | ErrorCode::TeamNotFound
+            | ErrorCode::PoolNotFound
+            | ErrorCode::LeaderboardNotFound => 404,
+            ErrorCode::NameExists | ErrorCode::PoolUnavailable | ErrorCode::InsufficientPoints => {
+                409
+            }
+            ErrorCode::PayloadTooLarge => 413,
+            ErrorCode::TooManyAttempts | ErrorCode::CollectCooldown | ErrorCode::TeamCooldown => {
+                429
+            }
+            ErrorCode::InternalError => 500,
+        }
+    }
+}
+
+/// Body of every error response, as `{"code": ..., "message": ..., "details": ...}`.
+///
+/// `message` is meant for humans and may change; clients should rely on `code`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ErrorBody {
+    pub code: ErrorCode,
+    pub message: String,
+    /// Extra data depending on the code, `null` when there is none.
+    pub details: Option<serde_json::Value>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_matches_code() {
+        assert_eq!(ErrorCode::InvalidRequest.status(), 400);
+        assert_eq!(ErrorCode::InvalidCredentials.status(), 401);
+        assert_eq!(ErrorCode::Unauthorized.status(), 401);
+        // Logged in users get a 403, a 401 would log them out.
+        assert_eq!(ErrorCode::WrongPassword.status(), 403);
+        assert_eq!(ErrorCode::Forbidden.status(), 403);
+        assert_eq!(ErrorCode::UserNotFound.status(), 404);
+        assert_eq!(ErrorCode::NameExists.status(), 409);
+        assert_eq!(ErrorCode::PayloadTooLarge.status(), 413);
+        assert_eq!(ErrorCode::TooManyAttempts.status(), 429);
+        assert_eq!(ErrorCode::InternalError.status(), 500);
+    }
+
+    #[test]
+    fn codes_serialize_in_snake_case() {
+        assert_eq!(
+            serde_json::to_string(&ErrorCode::TooManyAttempts).unwrap(),
+            r#""too_many_attempts""#
+        );
+        assert_eq!(
+            serde_json::from_str::<ErrorCode>(r#""invalid_request""#).unwrap(),
+            ErrorCode::InvalidRequest
+        );
+    }
+}
@@ -0,0 +1,51 @@
+use serde::Serialize;
+
+use crate::points::PoolStatus;
+
+/// Something that happened in the game, streamed by `/feed` as Server-Sent Events.
+///
+/// The event name is [`FeedEvent::kind`] and the data its fields, so they are
+/// serialized untagged; the fields alone don't tell the kinds apart.
+#[derive(Serialize, Debug)]
+#[serde(untagged)]
+pub enum FeedEvent {
+    Signup {
+        user_id: i32,
+        name: String,
+    },
+    Collect {
+        user_id: i32,
+        name: String,
+        pool_id: i32,
+        pool: String,
+    },
+    Assign {
+        user_id: i32,
+        name: String,
+        champion_id: i32,
+        amount: i32,
+        /// Total points of the champion after the assignment.
+        champion_points: i32,
+    },
+    Refill {
+        amount: i32,
+        pool: PoolStatus,
+    },
+    /// An admin changed the points of a pool, `amount` is negative when lowered.
+    Adjust {
+        amount: i32,
+        pool: PoolStatus,
+    },
+}
+
+impl FeedEvent {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            FeedEvent::Signup { .. } => "signup",
+            FeedEvent::Collect { .. } => "collect",
+            FeedEvent::Assign { .. } => "assign",
+            FeedEvent::Refill { .. } => "refill",
+            FeedEvent::Adjust { .. } => "adjust",
+        }
+    }
+}
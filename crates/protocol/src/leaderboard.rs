@@ -0,0 +1,31 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct Pagination {
+    /// At most 100, 20 when absent.
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+/// One row of a leaderboard. Ties share the same rank, and the next rank is skipped
+/// (1, 1, 3), then they are ordered by name.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct Entry {
+    pub rank: i64,
+    pub id: i32,
+    pub name: String,
+    pub points: i64,
+    /// Team of the champion or player, absent on the teams board and for teamless players.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub team_id: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Leaderboard {
+    /// Number of entries over all pages.
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64,
+    pub entries: Vec<Entry>,
+}
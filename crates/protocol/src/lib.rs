@@ -0,0 +1,14 @@
+//! Types exchanged between the server and its clients, and the paths they are sent to.
+//!
+//! Both sides use these, so a change to the API that breaks a client fails to compile.
+
+pub mod auth;
+pub mod error;
+pub mod feed;
+pub mod leaderboard;
+pub mod live;
+pub mod password;
+pub mod points;
+pub mod routes;
+pub mod teams;
+pub mod users;
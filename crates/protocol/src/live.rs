@@ -0,0 +1,27 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{leaderboard::Entry, points::PoolStatus};
+
+/// Query of the `/ws` upgrade request.
+#[derive(Serialize, Deserialize, Default)]
+pub struct WsParams {
+    /// For clients that can't set headers on a WebSocket, like browsers without the cookie.
+    pub token: Option<String>,
+}
+
+/// Change to the game state, pushed to the clients connected to `/ws` as JSON
+/// tagged with its `type`.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum GameEvent {
+    ChampionPoints {
+        champion_id: i32,
+        points: i32,
+    },
+    /// A pool emptied, or got refilled and will open at `open_at`.
+    Pool(PoolStatus),
+    Leaderboard {
+        board: String,
+        entries: Vec<Entry>,
+    },
+}
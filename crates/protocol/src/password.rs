@@ -0,0 +1,51 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+#[derive(Serialize, Deserialize)]
+pub struct PasswordChangeData {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PasswordResetData {
+    pub name: String,
+    pub code: String,
+    pub new_password: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ResetCodeData {
+    pub code: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub expires_at: OffsetDateTime,
+}
+
+/// Why a password was refused by the server's password policy.
+///
+/// Sent as the error details, e.g. `{"rule": "too_short", "min_length": 8}`.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "rule", rename_all = "snake_case")]
+pub enum PolicyViolation {
+    TooShort { min_length: usize },
+    TooLong { max_length: usize },
+    TooCommon,
+    SameAsName,
+}
+
+impl Display for PolicyViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PolicyViolation::TooShort { min_length } => {
+                write!(f, "must be at least {} characters long", min_length)
+            }
+            PolicyViolation::TooLong { max_length } => {
+                write!(f, "must be at most {} characters long", max_length)
+            }
+            PolicyViolation::TooCommon => f.write_str("is too common"),
+            PolicyViolation::SameAsName => f.write_str("must not be the user name"),
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+/// Name of the pool used by clients that don't name one.
+pub const GLOBAL_POOL: &str = "global";
+
+/// Body of [`crate::routes::ASSIGN`], which may be left out to assign a single point.
+#[derive(Serialize, Deserialize)]
+pub struct AssignData {
+    #[serde(default = "AssignData::default_amount")]
+    pub amount: i32,
+}
+
+impl AssignData {
+    fn default_amount() -> i32 {
+        1
+    }
+}
+
+impl Default for AssignData {
+    fn default() -> Self {
+        Self {
+            amount: Self::default_amount(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AssignResult {
+    pub champion_id: i32,
+    /// Total points of the champion after the assignment.
+    pub champion_points: i32,
+    /// Points the user has left.
+    pub points: i32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PoolStatus {
+    pub id: i32,
+    pub name: String,
+    /// Points left to collect.
+    pub points: i32,
+    /// Whether a collect can succeed right now.
+    pub open: bool,
+    /// When the pool opens, in the past when it is already open.
+    #[serde(with = "time::serde::rfc3339")]
+    pub open_at: OffsetDateTime,
+    /// Only members of this team may collect, anyone when absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub team_id: Option<i32>,
+}
+
+/// Settings of a pool; those left out use the server's defaults.
+#[derive(Serialize, Deserialize, Default)]
+pub struct PoolSettings {
+    /// Most points the pool holds after a refill.
+    pub capacity: Option<i32>,
+    pub refill_points: Option<i32>,
+    pub reopen_delay_min_secs: Option<i32>,
+    pub reopen_delay_max_secs: Option<i32>,
+    pub team_id: Option<i32>,
+}
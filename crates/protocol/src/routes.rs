@@ -0,0 +1,68 @@
+//! Paths of the API, in the syntax of the server's router where `:name` is a parameter.
+//!
+//! Clients fill the parameters in with the function named after the path.
+
+pub const SIGNUP: &str = "/users/signup";
+pub const LOGIN: &str = "/users/login";
+pub const LOGOUT: &str = "/users/logout";
+pub const ME: &str = "/users/me";
+pub const SESSIONS: &str = "/users/sessions";
+pub const SESSION: &str = "/users/sessions/:id";
+pub const PASSWORD: &str = "/users/password";
+pub const PASSWORD_RESET: &str = "/users/password/reset";
+
+pub const CHAMPIONS: &str = "/champions";
+pub const TEAMS: &str = "/teams";
+pub const TEAM_JOIN: &str = "/teams/:id/join";
+pub const TEAM_LEAVE: &str = "/teams/leave";
+pub const LEADERBOARD: &str = "/leaderboard/:board";
+
+pub const COLLECT: &str = "/points/collect";
+pub const POOL: &str = "/points/pool";
+pub const POOLS: &str = "/points/pools";
+pub const NAMED_POOL: &str = "/points/pools/:name";
+pub const NAMED_POOL_COLLECT: &str = "/points/pools/:name/collect";
+pub const ASSIGN: &str = "/points/assign/:id";
+
+pub const WS: &str = "/ws";
+pub const FEED: &str = "/feed";
+
+pub const ADMIN_POOL: &str = "/admin/pools/:name";
+pub const ADMIN_RESET_CODE: &str = "/admin/users/:name/reset-code";
+pub const ADMIN_USER_ROLE: &str = "/admin/users/:name/role";
+
+pub fn session(id: i32) -> String {
+    format!("/users/sessions/{}", id)
+}
+
+pub fn team_join(team_id: i32) -> String {
+    format!("/teams/{}/join", team_id)
+}
+
+pub fn leaderboard(board: &str) -> String {
+    format!("/leaderboard/{}", board)
+}
+
+pub fn named_pool(name: &str) -> String {
+    format!("/points/pools/{}", name)
+}
+
+pub fn named_pool_collect(name: &str) -> String {
+    format!("/points/pools/{}/collect", name)
+}
+
+pub fn assign(champion_id: i32) -> String {
+    format!("/points/assign/{}", champion_id)
+}
+
+pub fn admin_pool(name: &str) -> String {
+    format!("/admin/pools/{}", name)
+}
+
+pub fn admin_reset_code(name: &str) -> String {
+    format!("/admin/users/{}/reset-code", name)
+}
+
+pub fn admin_user_role(name: &str) -> String {
+    format!("/admin/users/{}/role", name)
+}
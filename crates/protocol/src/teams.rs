@@ -0,0 +1,22 @@
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+#[derive(Serialize, Deserialize)]
+pub struct Champion {
+    pub id: i32,
+    pub team_id: i32,
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Team {
+    pub id: i32,
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Membership {
+    pub team_id: Option<i32>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub next_change_at: OffsetDateTime,
+}
@@ -0,0 +1,35 @@
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::auth::Role;
+
+#[derive(Serialize, Deserialize)]
+pub struct TeamInfo {
+    pub id: i32,
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ChampionContribution {
+    pub champion_id: i32,
+    pub name: String,
+    pub points: i32,
+}
+
+/// Everything the client needs to show the player's state.
+#[derive(Serialize, Deserialize)]
+pub struct Profile {
+    pub id: i32,
+    pub name: String,
+    pub role: Role,
+    /// Points not assigned yet.
+    pub points: i32,
+    pub team: Option<TeamInfo>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub next_collect_at: OffsetDateTime,
+    pub can_collect: bool,
+    /// Sum of `champions`.
+    pub total_assigned: i64,
+    /// Points given to each champion, most supported first.
+    pub champions: Vec<ChampionContribution>,
+}
@@ -0,0 +1,6 @@
+drop index if exists sessions_expires_at;
+
+alter table sessions
+    drop column expires_at,
+    drop column last_seen_at,
+    drop column created_at;
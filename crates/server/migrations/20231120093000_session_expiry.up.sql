@@ -0,0 +1,7 @@
+-- Sessions now expire: absolute expiry (slid forward on activity) and idle timeout.
+alter table sessions
+    add column created_at timestamp not null DEFAULT NOW(),
+    add column last_seen_at timestamp not null DEFAULT NOW(),
+    add column expires_at timestamp not null DEFAULT NOW() + CAST('7 days' AS INTERVAL);
+
+create index if not exists sessions_expires_at on sessions (expires_at);
@@ -0,0 +1,2 @@
+-- Public identifier for sessions, so users can list and revoke them without seeing tokens.
+alter table sessions add column id serial not null unique;
@@ -0,0 +1,8 @@
+-- Failed logins per account name or IP, to throttle password guessing.
+create table if not exists login_attempts
+(
+    key varchar(128) primary key,
+    failures int not null DEFAULT 0,
+    last_failure_at timestamp not null DEFAULT NOW(),
+    blocked_until timestamp not null DEFAULT NOW()
+);
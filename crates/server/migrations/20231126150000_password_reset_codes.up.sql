@@ -0,0 +1,10 @@
+-- One-time codes issued by an admin so a player can set a new password.
+create table if not exists password_reset_codes
+(
+    id serial primary key,
+    user_id int references users (id) on delete cascade not null,
+    code_hash text not null,
+    created_at timestamp not null DEFAULT NOW(),
+    expires_at timestamp not null,
+    used_at timestamp
+);
@@ -0,0 +1,3 @@
+-- Ordered from least to most privileged, roles compare with < and >.
+create type user_role as enum ('player', 'moderator', 'admin');
+alter table users add column role user_role not null default 'player';
@@ -0,0 +1 @@
+drop table champion_contributions;
@@ -0,0 +1,8 @@
+-- Points each user gave to each champion, for player stats.
+create table if not exists champion_contributions
+(
+    user_id int references users (id) on delete cascade not null,
+    champion_id int references champions (id) on delete cascade not null,
+    points int not null default 0,
+    primary key (user_id, champion_id)
+);
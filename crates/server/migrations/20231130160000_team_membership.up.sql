@@ -0,0 +1,2 @@
+-- Last time the user joined, left or switched team, for the switch cooldown.
+alter table users add column team_changed_at timestamp;
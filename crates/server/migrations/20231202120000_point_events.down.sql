@@ -0,0 +1,3 @@
+drop table point_events;
+drop function point_events_append_only;
+drop type point_event_kind;
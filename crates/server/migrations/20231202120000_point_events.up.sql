@@ -0,0 +1,35 @@
+create type point_event_kind as enum ('opening', 'collect', 'assign', 'refill');
+
+-- Every change to a points balance, so balances can be audited and rebuilt:
+-- user = opening + collect - assign, champion = opening + assign,
+-- pool = opening + refill - collect.
+-- Ids are not foreign keys, the history must outlive what it refers to.
+create table if not exists point_events
+(
+    id bigserial primary key,
+    kind point_event_kind not null,
+    -- Who did it, null for the server itself.
+    user_id int,
+    champion_id int,
+    pool_id int,
+    amount int not null,
+    created_at timestamp not null default NOW()
+);
+create index point_events_user_id on point_events (user_id);
+create index point_events_champion_id on point_events (champion_id);
+
+create function point_events_append_only() returns trigger language plpgsql as $$
+begin
+    raise exception 'point_events is append-only';
+end;
+$$;
+create trigger point_events_append_only before update or delete or truncate on point_events
+    for each statement execute function point_events_append_only();
+
+-- Balances from before the ledger.
+insert into point_events (kind, user_id, amount)
+    select 'opening', id, points from users where points > 0;
+insert into point_events (kind, champion_id, amount)
+    select 'opening', id, points from champions where points > 0;
+insert into point_events (kind, pool_id, amount)
+    select 'opening', id, points from points_pool where points > 0;
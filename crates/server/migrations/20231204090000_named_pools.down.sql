@@ -0,0 +1,8 @@
+alter table points_pool drop column team_id;
+alter table points_pool drop constraint points_pool_reopen_delay_check;
+alter table points_pool drop column reopen_delay_max_secs;
+alter table points_pool drop column reopen_delay_min_secs;
+alter table points_pool drop column refill_points;
+alter table points_pool drop column capacity;
+alter table points_pool drop constraint points_pool_name_key;
+alter table points_pool drop column name;
@@ -0,0 +1,16 @@
+-- Pools are collected by name; the one from before is the global pool.
+alter table points_pool add column name varchar(32);
+update points_pool set name = 'global' where id = (select min(id) from points_pool);
+update points_pool set name = 'pool-' || id where name is null;
+alter table points_pool alter column name set not null;
+alter table points_pool add constraint points_pool_name_key unique (name);
+
+-- Per pool overrides of the economy config, null means the default.
+alter table points_pool add column capacity int check (capacity > 0);
+alter table points_pool add column refill_points int check (refill_points > 0);
+alter table points_pool add column reopen_delay_min_secs int check (reopen_delay_min_secs >= 0);
+alter table points_pool add column reopen_delay_max_secs int check (reopen_delay_max_secs >= 0);
+alter table points_pool add constraint points_pool_reopen_delay_check
+    check (reopen_delay_max_secs >= reopen_delay_min_secs);
+-- Only members of this team may collect from the pool.
+alter table points_pool add column team_id int references teams (id) on delete cascade;
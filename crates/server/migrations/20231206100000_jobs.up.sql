@@ -0,0 +1,17 @@
+-- When each background job last ran, shared by all server instances.
+create table if not exists job_runs
+(
+    name varchar(32) primary key,
+    last_run_at timestamp not null
+);
+
+-- Periodic copies of the leaderboards, to show how standings evolve.
+create table if not exists leaderboard_snapshots
+(
+    taken_at timestamp not null,
+    board varchar(16) not null,
+    entity_id int not null,
+    rank bigint not null,
+    points bigint not null,
+    primary key (board, taken_at, entity_id)
+);
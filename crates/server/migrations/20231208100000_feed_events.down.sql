@@ -0,0 +1,2 @@
+drop table feed_events;
+drop type feed_event_kind;
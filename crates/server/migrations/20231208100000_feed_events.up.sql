@@ -0,0 +1,12 @@
+create type feed_event_kind as enum ('signup', 'collect', 'assign', 'refill');
+
+-- What happened in the game, for the `/feed` event stream. Ids are in commit order,
+-- so a client resuming after an id misses nothing. Old events are pruned.
+create table if not exists feed_events
+(
+    id bigserial primary key,
+    kind feed_event_kind not null,
+    data json not null,
+    created_at timestamp not null default NOW()
+);
+create index feed_events_created_at on feed_events (created_at);
@@ -0,0 +1 @@
+-- Postgres can't drop enum values; 'adjust' stays, unused by older servers.
@@ -0,0 +1,4 @@
+-- Points an admin takes out of a pool by lowering its capacity, so the ledger still adds up:
+-- pool = opening + refill + adjust - collect.
+alter type point_event_kind add value if not exists 'adjust';
+alter type feed_event_kind add value if not exists 'adjust';
@@ -1,18 +1,42 @@
-use std::{error::Error, fmt::Display, str::FromStr};
+use std::{
+    error::Error,
+    fmt::Display,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
-use crate::Random;
+use crate::{
+    config,
+    error::{ApiError, ErrorCode},
+    extract::{Json, Path},
+    feed,
+    hashing::PasswordHashing,
+    now_utc,
+    password::PasswordPolicy,
+    throttle::{client_ip, AttemptKey, LoginThrottle, MAX_NAME_LENGTH},
+    Random,
+};
 use axum::{
-    body, extract,
-    extract::{Path, State},
-    http::StatusCode,
+    async_trait, body,
+    extract::{FromRequestParts, State},
+    http::{request::Parts, HeaderMap, StatusCode},
     response::IntoResponse,
     routing::{get, post},
-    Extension, Json, Router,
+    Extension, Router,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use protocol::{
+    auth::{LoginData, Role, RoleData, SessionData, SessionInfo, SignupData},
+    feed::FeedEvent,
+    password::PolicyViolation,
 };
-use password_auth::{generate_hash, verify_password};
 use rand_core::RngCore;
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
+use shuttle_secrets::SecretStore;
 use sqlx::{Database, PgPool};
+use time::PrimitiveDateTime;
+use tokio::sync::OnceCell;
 
 #[derive(Debug)]
 pub(crate) enum MultipartError {
@@ -39,7 +63,7 @@ pub(crate) enum SignupError {
     InvalidName,
     PasswordsDoNotMatch,
     MissingDetails,
-    InvalidPassword,
+    InvalidPassword(PolicyViolation),
     InternalError,
 }
 
@@ -50,7 +74,7 @@ impl Display for SignupError {
             SignupError::NameExists => f.write_str("User name already exists"),
             SignupError::PasswordsDoNotMatch => f.write_str("Passwords do not match"),
             SignupError::MissingDetails => f.write_str("Missing Details"),
-            SignupError::InvalidPassword => f.write_str("Invalid Password"),
+            SignupError::InvalidPassword(reason) => write!(f, "Invalid Password: {}", reason),
             SignupError::InternalError => f.write_str("Internal Error"),
         }
     }
@@ -58,9 +82,25 @@ impl Display for SignupError {
 
 impl Error for SignupError {}
 
+impl From<SignupError> for ApiError {
+    fn from(err: SignupError) -> Self {
+        let code = match &err {
+            SignupError::NameExists => ErrorCode::NameExists,
+            SignupError::InvalidName => ErrorCode::InvalidName,
+            SignupError::PasswordsDoNotMatch => ErrorCode::PasswordsDoNotMatch,
+            SignupError::MissingDetails => ErrorCode::MissingDetails,
+            SignupError::InvalidPassword(reason) => {
+                return ApiError::new(ErrorCode::InvalidPassword, &err).with_details(reason)
+            }
+            SignupError::InternalError => ErrorCode::InternalError,
+        };
+        ApiError::new(code, err)
+    }
+}
+
 impl IntoResponse for SignupError {
     fn into_response(self) -> axum::response::Response {
-        (StatusCode::BAD_REQUEST, self.to_string()).into_response()
+        ApiError::from(self).into_response()
     }
 }
 
@@ -69,6 +109,8 @@ pub(crate) enum LoginError {
     MissingDetails,
     UserDoesNotExist,
     WrongPassword,
+    TooManyAttempts(Duration),
+    InternalError,
 }
 
 impl Display for LoginError {
@@ -77,33 +119,50 @@ impl Display for LoginError {
             LoginError::UserDoesNotExist => f.write_str("User does not exist"),
             LoginError::MissingDetails => f.write_str("Missing details"),
             LoginError::WrongPassword => f.write_str("Wrong password"),
+            LoginError::TooManyAttempts(_) => f.write_str("Too many login attempts"),
+            LoginError::InternalError => f.write_str("Internal Error"),
+        }
+    }
+}
+
+/// The client never learns whether the name or the password was wrong, so it can't be
+/// used to find out which accounts exist. The detailed reason is logged instead.
+impl From<LoginError> for ApiError {
+    fn from(err: LoginError) -> Self {
+        match err {
+            LoginError::UserDoesNotExist | LoginError::WrongPassword => {
+                ApiError::new(ErrorCode::InvalidCredentials, "Wrong user name or password")
+            }
+            LoginError::TooManyAttempts(retry_after) => ApiError::new(
+                ErrorCode::TooManyAttempts,
+                "Too many login attempts, try again later",
+            )
+            .with_retry_after(retry_after),
+            LoginError::MissingDetails => ApiError::new(ErrorCode::MissingDetails, err),
+            LoginError::InternalError => ApiError::new(ErrorCode::InternalError, err),
         }
     }
 }
+
 impl IntoResponse for LoginError {
     fn into_response(self) -> axum::response::Response {
-        (StatusCode::BAD_REQUEST, self.to_string()).into_response()
+        ApiError::from(self).into_response()
     }
 }
 
 impl Error for LoginError {}
 
-#[derive(Serialize, Deserialize)]
-pub struct SignupData {
-    pub name: String,
-    pub password: String,
-}
-
-#[derive(Serialize, Deserialize)]
-pub struct LoginData {
-    pub name: String,
-    pub password: String,
-}
-
 pub async fn post_signup(
     Extension(random): Extension<Random>,
     Extension(database): Extension<PgPool>,
-    Json::<SignupData>(SignupData { name, password }): extract::Json<SignupData>,
+    Extension(session_config): Extension<Arc<SessionConfig>>,
+    Extension(password_policy): Extension<Arc<PasswordPolicy>>,
+    Extension(hashing): Extension<Arc<PasswordHashing>>,
+    Json(SignupData {
+        name,
+        password,
+        password_confirm,
+    }): Json<SignupData>,
 ) -> impl IntoResponse {
     fn valid_username(name: &str) -> bool {
         (1..20).contains(&name.len())
@@ -112,72 +171,349 @@ pub async fn post_signup(
                 .all(|c| matches!(c, 'a'..='z' | 'A'..='Z' | '0'..='9' | '-'))
     }
 
+    if name.is_empty() || password.is_empty() {
+        return Err(SignupError::MissingDetails);
+    }
     if !valid_username(&name) {
         return Err(SignupError::InvalidName);
     }
+    if password_confirm.is_some_and(|confirm| confirm != password) {
+        return Err(SignupError::PasswordsDoNotMatch);
+    }
+    password_policy
+        .check(&name, &password)
+        .map_err(SignupError::InvalidPassword)?;
     const INSERT_QUERY: &str = "INSERT INTO users (name, password) VALUES ($1, $2) RETURNING id;";
 
-    // Hash password to PHC string ($pbkdf2-sha256$...)
-    let hashed_password = generate_hash(password);
+    // Hash password to PHC string ($argon2id$...)
+    let hashed_password = hashing.hash(password).await;
+    let internal_error = |err: sqlx::Error| {
+        tracing::error!("Signup failed on a database error: {}", err);
+        SignupError::InternalError
+    };
+    let mut transaction = database.begin().await.map_err(internal_error)?;
     let fetch_one = sqlx::query_as(INSERT_QUERY)
-        .bind(name)
+        .bind(&name)
         .bind(hashed_password)
-        .fetch_one(&database)
+        .fetch_one(&mut *transaction)
         .await;
     let user_id: i32 = match fetch_one {
         Ok((user_id,)) => user_id,
         Err(sqlx::Error::Database(database)) if database.constraint() == Some("users_name_key") => {
             return Err(SignupError::NameExists);
         }
-        Err(_err) => {
-            return Err(SignupError::InternalError);
-        }
+        Err(err) => return Err(internal_error(err)),
     };
-    let session = new_session(&database, random, user_id).await;
-    let response = session.to_response();
+    feed::record(&mut transaction, &FeedEvent::Signup { user_id, name })
+        .await
+        .map_err(internal_error)?;
+    transaction.commit().await.map_err(internal_error)?;
+    let session = new_session(&database, random, &session_config, user_id)
+        .await
+        .map_err(|err| {
+            tracing::error!("Signup failed to open a session: {}", err);
+            SignupError::InternalError
+        })?;
+    let response = session.to_response(&session_config.cookie, session_config.lifetime);
     Ok(response)
 }
 
 pub(crate) async fn post_login(
     Extension(random): Extension<Random>,
     Extension(database): Extension<PgPool>,
-    Json::<LoginData>(LoginData { name, password }): extract::Json<LoginData>,
+    Extension(session_config): Extension<Arc<SessionConfig>>,
+    Extension(throttle): Extension<Arc<LoginThrottle>>,
+    Extension(hashing): Extension<Arc<PasswordHashing>>,
+    headers: HeaderMap,
+    Json(LoginData { name, password }): Json<LoginData>,
 ) -> impl IntoResponse {
     const LOGIN_QUERY: &str = "SELECT id, password FROM users WHERE users.name = $1";
+    // Only if unchanged, so a concurrent password change wins.
+    const REHASH_QUERY: &str = "UPDATE users SET password = $3 WHERE id = $1 AND password = $2;";
 
-    let row: Option<(i32, String)> = sqlx::query_as(LOGIN_QUERY)
-        .bind(name)
-        .fetch_optional(&database)
-        .await
-        .unwrap();
+    /// Checked against when the user does not exist, so that takes as long as a wrong password.
+    static DUMMY_HASH: OnceCell<String> = OnceCell::const_new();
 
-    let (user_id, correct_hashed_password) = if let Some(row) = row {
-        row
-    } else {
+    if name.is_empty() || password.is_empty() {
+        return Err(LoginError::MissingDetails);
+    }
+    // Can't be an account, and would only fill `login_attempts` with junk.
+    if name.chars().count() > MAX_NAME_LENGTH {
+        tracing::info!(?name, "Login failed: name too long");
         return Err(LoginError::UserDoesNotExist);
+    }
+    let ip = client_ip(&headers);
+    let keys: Vec<AttemptKey> = [
+        Some(AttemptKey::Account(name.clone())),
+        ip.map(AttemptKey::Ip),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    let internal_error = |err: sqlx::Error| {
+        tracing::error!("Login failed on a database error: {}", err);
+        LoginError::InternalError
+    };
+
+    let attempt = match throttle
+        .attempt(&database, keys)
+        .await
+        .map_err(internal_error)?
+    {
+        Ok(attempt) => attempt,
+        Err(retry_after) => {
+            tracing::warn!(%name, ?ip, "Login attempt while blocked");
+            return Err(LoginError::TooManyAttempts(retry_after));
+        }
     };
 
+    let row: Option<(i32, String)> = sqlx::query_as(LOGIN_QUERY)
+        .bind(&name)
+        .fetch_optional(&database)
+        .await
+        .map_err(internal_error)?;
+
     // Verify password against PHC string
-    if let Err(_err) = verify_password(password, &correct_hashed_password) {
-        return Err(LoginError::WrongPassword);
+    let verified = match row {
+        Some((user_id, correct_hashed_password)) => hashing
+            .verify(&password, &correct_hashed_password)
+            .await
+            .map(|()| (user_id, correct_hashed_password))
+            .map_err(|_| LoginError::WrongPassword),
+        None => {
+            let dummy_hash = DUMMY_HASH
+                .get_or_init(|| hashing.hash("not a password"))
+                .await;
+            let _ = hashing.verify(&password, dummy_hash).await;
+            Err(LoginError::UserDoesNotExist)
+        }
+    };
+    let (user_id, correct_hashed_password) = match verified {
+        Ok(verified) => verified,
+        Err(err) => {
+            // Already counted by the attempt.
+            tracing::info!(%name, ?ip, "Login failed: {}", err);
+            return Err(err);
+        }
+    };
+    throttle
+        .record_success(&database, attempt)
+        .await
+        .map_err(internal_error)?;
+
+    // The password is only known now, upgrade hashes from older schemes or costs.
+    if hashing.needs_rehash(&correct_hashed_password) {
+        let rehashed = sqlx::query(REHASH_QUERY)
+            .bind(user_id)
+            .bind(&correct_hashed_password)
+            .bind(hashing.hash(&password).await)
+            .execute(&database)
+            .await;
+        match rehashed {
+            Ok(_) => tracing::info!(%name, "Upgraded password hash"),
+            // Not worth failing the login for, it will be tried again next time.
+            Err(err) => tracing::warn!(%name, "Failed to upgrade password hash: {}", err),
+        }
     }
-    let session = new_session(&database, random, user_id).await;
-    let response = session.to_response();
+
+    let session = new_session(&database, random, &session_config, user_id)
+        .await
+        .map_err(internal_error)?;
+    let response = session.to_response(&session_config.cookie, session_config.lifetime);
 
     Ok(response)
 }
 
+/// Ends the current session, and asks the browser to forget its cookie.
+pub(crate) async fn post_logout(
+    current_user: AuthState,
+    Extension(session_config): Extension<Arc<SessionConfig>>,
+) -> Result<impl IntoResponse, ApiError> {
+    const QUERY: &str = "DELETE FROM sessions WHERE session_token = $1;";
+
+    if let Some(session) = current_user.0 {
+        sqlx::query(QUERY)
+            .bind(session.token.into_database_value())
+            .execute(&session.database)
+            .await?;
+    }
+    Ok((
+        StatusCode::OK,
+        [(
+            http::header::SET_COOKIE,
+            session_config.cookie.clear_cookie(),
+        )],
+    ))
+}
+
+/// Lists the active sessions of the current user, most recently used first.
+pub(crate) async fn get_sessions(
+    RequireUser(user, token): RequireUser,
+    Extension(database): Extension<PgPool>,
+    Extension(session_config): Extension<Arc<SessionConfig>>,
+) -> Result<impl IntoResponse, ApiError> {
+    // Idle sessions are refused already, even before the cleanup job deletes them.
+    const QUERY: &str = "SELECT id, created_at, last_seen_at, expires_at, session_token = $2
+        FROM sessions
+        WHERE user_id = $1 AND expires_at > $3 AND last_seen_at > $4
+        ORDER BY last_seen_at DESC;";
+
+    let now = now_utc();
+
+    let rows: Vec<(
+        i32,
+        PrimitiveDateTime,
+        PrimitiveDateTime,
+        PrimitiveDateTime,
+        bool,
+    )> = sqlx::query_as(QUERY)
+        .bind(user.id)
+        .bind(token.into_database_value())
+        .bind(now)
+        .bind(now - session_config.idle_timeout)
+        .fetch_all(&database)
+        .await?;
+    let sessions: Vec<SessionInfo> = rows
+        .into_iter()
+        .map(
+            |(id, created_at, last_seen_at, expires_at, current)| SessionInfo {
+                id,
+                created_at: created_at.assume_utc(),
+                last_seen_at: last_seen_at.assume_utc(),
+                expires_at: expires_at.assume_utc(),
+                current,
+            },
+        )
+        .collect();
+    Ok((StatusCode::OK, Json(sessions)))
+}
+
+/// Revokes one of the current user's sessions, typically one left open on another device.
+pub(crate) async fn delete_session(
+    RequireUser(user, _): RequireUser,
+    Extension(database): Extension<PgPool>,
+    Path(session_id): Path<i32>,
+) -> Result<impl IntoResponse, ApiError> {
+    const QUERY: &str = "DELETE FROM sessions WHERE id = $1 AND user_id = $2;";
+
+    let result = sqlx::query(QUERY)
+        .bind(session_id)
+        .bind(user.id)
+        .execute(&database)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(ApiError::new(
+            ErrorCode::SessionNotFound,
+            "No such session.",
+        ));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
 const USER_COOKIE_NAME: &str = "user_token";
-const COOKIE_MAX_AGE: &str = "9999999";
+
+/// Lifetime rules for sessions, read from the secrets at startup.
+pub(crate) struct SessionConfig {
+    /// How long a session stays valid after being issued or renewed.
+    pub lifetime: Duration,
+    /// Absolute limit counted from creation, renewals never go past it.
+    pub max_lifetime: Duration,
+    /// A session not used for this long is rejected.
+    pub idle_timeout: Duration,
+    /// A session used when it has less than this left is renewed.
+    pub renew_before: Duration,
+    pub cookie: CookieConfig,
+}
+
+impl SessionConfig {
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    pub fn from_secrets(secrets: &SecretStore) -> Self {
+        Self {
+            lifetime: config::get_secs(secrets, "SESSION_LIFETIME_SECS", 7 * Self::DAY),
+            max_lifetime: config::get_secs(secrets, "SESSION_MAX_LIFETIME_SECS", 90 * Self::DAY),
+            idle_timeout: config::get_secs(secrets, "SESSION_IDLE_TIMEOUT_SECS", 3 * Self::DAY),
+            renew_before: config::get_secs(secrets, "SESSION_RENEW_BEFORE_SECS", 2 * Self::DAY),
+            cookie: CookieConfig::from_secrets(secrets),
+        }
+    }
+}
+
+/// Attributes of the session cookie.
+pub(crate) struct CookieConfig {
+    pub http_only: bool,
+    /// Only send the cookie over HTTPS; turn off for local development over plain HTTP.
+    pub secure: bool,
+    pub same_site: cookie::SameSite,
+    pub domain: Option<String>,
+}
+
+impl CookieConfig {
+    pub fn from_secrets(secrets: &SecretStore) -> Self {
+        let same_site = match secrets.get("COOKIE_SAME_SITE").as_deref() {
+            None | Some("Lax") => cookie::SameSite::Lax,
+            Some("Strict") => cookie::SameSite::Strict,
+            Some("None") => cookie::SameSite::None,
+            Some(other) => panic!(
+                "Invalid value for secret `COOKIE_SAME_SITE`: {}, expected Lax, Strict or None",
+                other
+            ),
+        };
+        Self {
+            http_only: config::get_or(secrets, "COOKIE_HTTP_ONLY", true),
+            secure: config::get_or(secrets, "COOKIE_SECURE", true),
+            same_site,
+            domain: secrets.get("COOKIE_DOMAIN"),
+        }
+    }
+
+    /// Builds the `Set-Cookie` value for the session cookie.
+    fn build(&self, value: String, max_age: Duration) -> String {
+        let mut cookie = cookie::Cookie::build(USER_COOKIE_NAME, value)
+            .path("/")
+            .max_age(time::Duration::seconds(max_age.as_secs() as i64))
+            .http_only(self.http_only)
+            .secure(self.secure)
+            .same_site(self.same_site);
+        if let Some(domain) = &self.domain {
+            cookie = cookie.domain(domain.clone());
+        }
+        cookie.finish().to_string()
+    }
+
+    /// Cookie telling the browser to drop the session token.
+    pub fn clear_cookie(&self) -> String {
+        self.build(String::new(), Duration::ZERO)
+    }
+}
 
 #[derive(Clone, Copy)]
 pub(crate) struct SessionToken(u128);
 
+#[derive(Debug)]
+pub(crate) struct InvalidSessionToken;
+
+impl Display for InvalidSessionToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Invalid session token")
+    }
+}
+
+impl Error for InvalidSessionToken {}
+
 impl FromStr for SessionToken {
-    type Err = <u128 as FromStr>::Err;
+    type Err = InvalidSessionToken;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        s.parse().map(Self)
+        // Tokens are 16 bytes, so always 22 characters once encoded.
+        if s.len() == 22 {
+            let bytes = URL_SAFE_NO_PAD.decode(s).map_err(|_| InvalidSessionToken)?;
+            let bytes = bytes.try_into().map_err(|_| InvalidSessionToken)?;
+            return Ok(Self(u128::from_le_bytes(bytes)));
+        }
+        // TODO: Drop the decimal form once sessions issued before the switch have expired,
+        // see `SESSION_MAX_LIFETIME_SECS`.
+        s.parse().map(Self).map_err(|_| InvalidSessionToken)
     }
 }
 
@@ -189,28 +525,30 @@ impl SessionToken {
     }
 
     pub fn into_cookie_value(self) -> String {
-        // TODO Opportunity for a smaller format that is still a valid cookie value
-        self.0.to_string()
+        URL_SAFE_NO_PAD.encode(self.0.to_le_bytes())
     }
 
     pub fn into_database_value(self) -> Vec<u8> {
         self.0.to_le_bytes().to_vec()
     }
-    pub fn to_response(self) -> http::Response<String> {
-        http::Response::builder()
-            .status(http::StatusCode::OK)
-            .header("Location", "/")
-            .header(
-                "Set-Cookie",
-                format!(
-                    "{}={}; Max-Age={}; Path=/",
-                    USER_COOKIE_NAME,
-                    self.into_cookie_value(),
-                    COOKIE_MAX_AGE
-                ),
-            )
-            .body("".to_string())
-            .unwrap()
+
+    pub fn to_cookie(self, config: &CookieConfig, max_age: Duration) -> String {
+        config.build(self.into_cookie_value(), max_age)
+    }
+
+    pub fn to_response(self, config: &CookieConfig, max_age: Duration) -> axum::response::Response {
+        (
+            StatusCode::OK,
+            [
+                (http::header::LOCATION, "/".to_string()),
+                (http::header::SET_COOKIE, self.to_cookie(config, max_age)),
+            ],
+            Json(SessionData {
+                token: self.into_cookie_value(),
+                expires_in: max_age.as_secs(),
+            }),
+        )
+            .into_response()
     }
 }
 
@@ -218,46 +556,265 @@ impl SessionToken {
 pub(crate) struct User {
     pub id: i32,
     pub name: String,
+    pub role: Role,
+}
+
+/// Cookie to send back once the request is handled, set when the session got renewed.
+type Renewal = Arc<Mutex<Option<(SessionToken, Duration)>>>;
+
+#[derive(Clone)]
+struct Session {
+    token: SessionToken,
+    user: Option<User>,
+    database: PgPool,
+    config: Arc<SessionConfig>,
+    renewal: Renewal,
+}
+
+impl Session {
+    /// Marks the session as used now, sliding its expiry forward if it is close to it.
+    async fn touch(
+        &self,
+        now: PrimitiveDateTime,
+        created_at: PrimitiveDateTime,
+        expires_at: PrimitiveDateTime,
+    ) -> Result<(), sqlx::Error> {
+        const QUERY: &str =
+            "UPDATE sessions SET last_seen_at = $2, expires_at = $3 WHERE session_token = $1;";
+
+        let mut new_expires_at = expires_at;
+        if expires_at - now < self.config.renew_before {
+            new_expires_at = (now + self.config.lifetime)
+                .min(created_at + self.config.max_lifetime)
+                .max(expires_at);
+        }
+
+        sqlx::query(QUERY)
+            .bind(self.token.into_database_value())
+            .bind(now)
+            .bind(new_expires_at)
+            .execute(&self.database)
+            .await?;
+
+        if new_expires_at > expires_at {
+            let max_age = (new_expires_at - now).try_into().unwrap_or_default();
+            *self.renewal.lock().unwrap() = Some((self.token, max_age));
+        }
+        Ok(())
+    }
 }
 
+/// Session of the current request, if it came with a token.
+///
+/// Usable as an extractor; the user is only loaded from the database on the first
+/// call to [`AuthState::get_user`].
 #[derive(Clone)]
-pub(crate) struct AuthState(Option<(SessionToken, Option<User>, PgPool)>);
+pub(crate) struct AuthState(Option<Session>);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for AuthState {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        if let Some(auth_state) = parts.extensions.get::<AuthState>() {
+            return Ok(auth_state.clone());
+        }
+        // The auth middleware did not run for this route: build the state from the
+        // request itself, without sliding renewal since nobody will send the cookie back.
+        let database = parts.extensions.get::<PgPool>().cloned();
+        let config = parts.extensions.get::<Arc<SessionConfig>>().cloned();
+        let session = session_token_from_headers(&parts.headers)
+            .zip(database.zip(config))
+            .map(|(token, (database, config))| AuthState::from_token(token, database, config));
+        Ok(session.unwrap_or(AuthState(None)))
+    }
+}
+
+/// Rejection for requests needing a logged in user.
+pub(crate) struct Unauthorized;
+
+impl From<Unauthorized> for ApiError {
+    fn from(_: Unauthorized) -> Self {
+        ApiError::new(ErrorCode::Unauthorized, "You must be logged in.")
+    }
+}
+
+impl IntoResponse for Unauthorized {
+    fn into_response(self) -> axum::response::Response {
+        ApiError::from(self).into_response()
+    }
+}
+
+/// Extractor for handlers that need a logged in user, rejecting the request otherwise.
+///
+/// Comes with the token of the session, for handlers treating the current one apart.
+pub(crate) struct RequireUser(pub User, pub SessionToken);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for RequireUser {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Ok(mut auth_state) = AuthState::from_request_parts(parts, state).await;
+        let user = auth_state.get_user().await?.cloned();
+        let token = auth_state.token();
+        // Keep the loaded user around so a later `AuthState` extractor doesn't query it again.
+        parts.extensions.insert(auth_state);
+        match (user, token) {
+            (Some(user), Some(token)) => Ok(RequireUser(user, token)),
+            _ => Err(Unauthorized.into()),
+        }
+    }
+}
+
+/// Rejection for requests from a user without the role an endpoint needs.
+pub(crate) struct Forbidden(pub Role);
+
+impl From<Forbidden> for ApiError {
+    fn from(Forbidden(role): Forbidden) -> Self {
+        ApiError::new(
+            ErrorCode::Forbidden,
+            format!("The {} role is required.", role),
+        )
+        .with_details(serde_json::json!({ "required_role": role }))
+    }
+}
+
+impl IntoResponse for Forbidden {
+    fn into_response(self) -> axum::response::Response {
+        ApiError::from(self).into_response()
+    }
+}
+
+/// Loads the logged in user like [`RequireUser`], and checks they have at least `role`.
+async fn require_role<S: Send + Sync>(
+    parts: &mut Parts,
+    state: &S,
+    role: Role,
+) -> Result<User, ApiError> {
+    let RequireUser(user, _) = RequireUser::from_request_parts(parts, state).await?;
+    if user.role < role {
+        tracing::warn!(name = %user.name, %role, "Access denied for missing role");
+        return Err(Forbidden(role).into());
+    }
+    Ok(user)
+}
+
+/// Extractor for handlers restricted to admins.
+pub(crate) struct RequireAdmin(pub User);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for RequireAdmin {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        require_role(parts, state, Role::Admin)
+            .await
+            .map(RequireAdmin)
+    }
+}
+
+/// Promotes the user named in the `BOOTSTRAP_ADMIN` secret, as long as there is no admin yet.
+///
+/// Meant for the first deployment: sign up, then set the secret to that name and redeploy.
+/// Once an admin exists this does nothing, and further roles are given with [`put_user_role`].
+pub(crate) async fn bootstrap_admin(database: &PgPool, name: &str) -> Result<(), sqlx::Error> {
+    const QUERY: &str = "UPDATE users SET role = 'admin'
+        WHERE name = $1 AND NOT EXISTS (SELECT 1 FROM users WHERE role = 'admin');";
+
+    let result = sqlx::query(QUERY).bind(name).execute(database).await?;
+    if result.rows_affected() > 0 {
+        tracing::info!(%name, "Promoted bootstrap admin");
+    }
+    Ok(())
+}
+
+/// Sets the role of a user. Their open sessions see the change on their next request.
+pub(crate) async fn put_user_role(
+    RequireAdmin(admin): RequireAdmin,
+    Extension(database): Extension<PgPool>,
+    Path(name): Path<String>,
+    Json(RoleData { role }): Json<RoleData>,
+) -> Result<impl IntoResponse, ApiError> {
+    const QUERY: &str = "UPDATE users SET role = $2 WHERE name = $1;";
+
+    let result = sqlx::query(QUERY)
+        .bind(&name)
+        .bind(role)
+        .execute(&database)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(ApiError::new(ErrorCode::UserNotFound, "No such user."));
+    }
+    tracing::info!(admin = %admin.name, %name, %role, "Changed user role");
+    Ok(StatusCode::NO_CONTENT)
+}
 
 impl AuthState {
-    pub async fn get_user(&mut self) -> Option<&User> {
-        let (session_token, store, database) = self.0.as_mut()?;
-        if store.is_none() {
-            const QUERY: &str =
-                "SELECT id, name FROM users JOIN sessions ON user_id = id WHERE session_token = $1;";
-
-            let user: Option<(i32, String)> = sqlx::query_as(QUERY)
-                .bind(&session_token.into_database_value())
-                .fetch_optional(&*database)
-                .await
-                .unwrap();
-
-            if let Some((id, name)) = user {
-                *store = Some(User { id, name });
+    /// State for a token that didn't come in the usual headers, e.g. a query parameter.
+    pub fn from_token(token: SessionToken, database: PgPool, config: Arc<SessionConfig>) -> Self {
+        AuthState(Some(Session {
+            token,
+            user: None,
+            database,
+            config,
+            renewal: Renewal::default(),
+        }))
+    }
+
+    pub fn token(&self) -> Option<SessionToken> {
+        self.0.as_ref().map(|session| session.token)
+    }
+
+    pub async fn get_user(&mut self) -> Result<Option<&User>, sqlx::Error> {
+        let Some(session) = self.0.as_mut() else {
+            return Ok(None);
+        };
+        if session.user.is_none() {
+            const QUERY: &str = "SELECT users.id, name, role, sessions.created_at, expires_at
+                FROM users JOIN sessions ON user_id = users.id
+                WHERE session_token = $1 AND expires_at > $2 AND last_seen_at > $3;";
+
+            let now = now_utc();
+            let user: Option<(i32, String, Role, PrimitiveDateTime, PrimitiveDateTime)> =
+                sqlx::query_as(QUERY)
+                    .bind(&session.token.into_database_value())
+                    .bind(now)
+                    .bind(now - session.config.idle_timeout)
+                    .fetch_optional(&session.database)
+                    .await?;
+
+            if let Some((id, name, role, created_at, expires_at)) = user {
+                session.touch(now, created_at, expires_at).await?;
+                session.user = Some(User { id, name, role });
             }
         }
-        store.as_ref()
+        Ok(session.user.as_ref())
     }
 }
 
-/// TODO date
-pub(crate) async fn new_session(database: &PgPool, random: Random, user_id: i32) -> SessionToken {
-    const QUERY: &str = "INSERT INTO sessions (session_token, user_id) VALUES ($1, $2);";
+pub(crate) async fn new_session(
+    database: &PgPool,
+    random: Random,
+    config: &SessionConfig,
+    user_id: i32,
+) -> Result<SessionToken, sqlx::Error> {
+    const QUERY: &str =
+        "INSERT INTO sessions (session_token, user_id, created_at, last_seen_at, expires_at)
+        VALUES ($1, $2, $3, $3, $4);";
 
     let session_token = SessionToken::generate_new(random);
+    let now = now_utc();
 
-    let _result = sqlx::query(QUERY)
+    sqlx::query(QUERY)
         .bind(&session_token.into_database_value())
         .bind(user_id)
+        .bind(now)
+        .bind(now + config.lifetime)
         .execute(database)
-        .await
-        .unwrap();
+        .await?;
 
-    session_token
+    Ok(session_token)
 }
 
 /// **AUTH MIDDLEWARE**
@@ -265,24 +822,77 @@ pub(crate) async fn auth<B>(
     mut req: http::Request<B>,
     next: axum::middleware::Next<B>,
     database: PgPool,
+    config: Arc<SessionConfig>,
 ) -> axum::response::Response {
-    let session_token = req
-        .headers()
-        .get_all("Cookie")
+    let session_token = session_token_from_headers(req.headers());
+
+    let renewal = Renewal::default();
+    req.extensions_mut()
+        .insert(AuthState(session_token.map(|token| Session {
+            token,
+            user: None,
+            database,
+            config: config.clone(),
+            renewal: renewal.clone(),
+        })));
+
+    let mut response = next.run(req).await;
+    if let Some((token, max_age)) = renewal.lock().unwrap().take() {
+        if let Ok(cookie) = http::HeaderValue::from_str(&token.to_cookie(&config.cookie, max_age)) {
+            response
+                .headers_mut()
+                .append(http::header::SET_COOKIE, cookie);
+        }
+    }
+    response
+}
+
+/// Finds the session token in the `Authorization` header, or else among the request cookies.
+fn session_token_from_headers(headers: &HeaderMap) -> Option<SessionToken> {
+    let bearer = headers
+        .get(http::header::AUTHORIZATION)
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| header.strip_prefix("Bearer "));
+    if let Some(token) = bearer {
+        return token.trim().parse::<SessionToken>().ok();
+    }
+    headers
+        .get_all(http::header::COOKIE)
         .iter()
-        .filter_map(|cookie| {
-            cookie
-                .to_str()
-                .ok()
-                .and_then(|cookie| cookie.parse::<cookie::Cookie>().ok())
-        })
+        .filter_map(|header| header.to_str().ok())
+        .flat_map(cookie::Cookie::split_parse)
+        .filter_map(Result::ok)
         .find_map(|cookie| {
             (cookie.name() == USER_COOKIE_NAME).then(move || cookie.value().to_owned())
         })
-        .and_then(|cookie_value| cookie_value.parse::<SessionToken>().ok());
+        .and_then(|cookie_value| cookie_value.parse::<SessionToken>().ok())
+}
 
-    req.extensions_mut()
-        .insert(AuthState(session_token.map(|v| (v, None, database))));
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    next.run(req).await
+    #[test]
+    fn session_token_parses_base64() {
+        let token = SessionToken(0x0123_4567_89ab_cdef_0123_4567_89ab_cdef);
+        let value = token.into_cookie_value();
+        assert_eq!(value.len(), 22);
+        assert_eq!(value.parse::<SessionToken>().unwrap().0, token.0);
+    }
+
+    #[test]
+    fn session_token_parses_legacy_decimal() {
+        let token: SessionToken = "340282366920938463463374607431768211455".parse().unwrap();
+        assert_eq!(token.0, u128::MAX);
+        assert_eq!("42".parse::<SessionToken>().unwrap().0, 42);
+    }
+
+    #[test]
+    fn session_token_rejects_garbage() {
+        assert!("".parse::<SessionToken>().is_err());
+        assert!("not a token".parse::<SessionToken>().is_err());
+        assert!("-1".parse::<SessionToken>().is_err());
+        // Right length, but not base64.
+        assert!("!!!!!!!!!!!!!!!!!!!!!!".parse::<SessionToken>().is_err());
+    }
 }
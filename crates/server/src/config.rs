@@ -0,0 +1,26 @@
+use std::{fmt::Display, str::FromStr, time::Duration};
+
+use shuttle_secrets::SecretStore;
+
+/// Reads `key` from the secrets, or returns `default` when it is not set.
+///
+/// Panics on a value that does not parse, so a typo in `Secrets.toml` stops the
+/// server at startup instead of silently falling back to the default.
+pub(crate) fn get_or<T>(secrets: &SecretStore, key: &str, default: T) -> T
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    match secrets.get(key) {
+        Some(value) => value
+            .trim()
+            .parse()
+            .unwrap_or_else(|e| panic!("Invalid value for secret `{}`: {}", key, e)),
+        None => default,
+    }
+}
+
+/// Reads a duration expressed in seconds.
+pub(crate) fn get_secs(secrets: &SecretStore, key: &str, default: Duration) -> Duration {
+    Duration::from_secs(get_or(secrets, key, default.as_secs()))
+}
@@ -0,0 +1,69 @@
+use std::time::Duration;
+
+use rand::Rng;
+use shuttle_secrets::SecretStore;
+
+use crate::{config, Random};
+
+/// Tunables of the points economy, from the `ECONOMY_*` secrets.
+///
+/// Each environment has its own secrets (`Secrets.dev.toml` locally), so an event can be
+/// tuned without recompiling.
+pub(crate) struct EconomyConfig {
+    /// Time a player waits between two collects.
+    pub collect_cooldown: Duration,
+    /// Points put back in an empty pool.
+    pub refill_points: i32,
+    /// An empty pool reopens after a random delay in this range.
+    pub reopen_delay_min: Duration,
+    pub reopen_delay_max: Duration,
+}
+
+impl EconomyConfig {
+    pub fn from_secrets(secrets: &SecretStore) -> Self {
+        let config = Self {
+            collect_cooldown: config::get_secs(
+                secrets,
+                "ECONOMY_COLLECT_COOLDOWN_SECS",
+                Duration::from_secs(12),
+            ),
+            refill_points: config::get_or(secrets, "ECONOMY_REFILL_POINTS", 200),
+            reopen_delay_min: config::get_secs(
+                secrets,
+                "ECONOMY_REOPEN_DELAY_MIN_SECS",
+                Duration::from_secs(2 * 60 * 60),
+            ),
+            reopen_delay_max: config::get_secs(
+                secrets,
+                "ECONOMY_REOPEN_DELAY_MAX_SECS",
+                Duration::from_secs(6 * 60 * 60),
+            ),
+        };
+        assert!(
+            config.refill_points > 0,
+            "ECONOMY_REFILL_POINTS must be positive"
+        );
+        assert!(
+            config.reopen_delay_min <= config.reopen_delay_max,
+            "ECONOMY_REOPEN_DELAY_MIN_SECS must not be above ECONOMY_REOPEN_DELAY_MAX_SECS"
+        );
+        config
+    }
+
+    /// Picks how long an empty pool stays closed, within the pool's own range where it has one.
+    pub fn reopen_delay(
+        &self,
+        random: &Random,
+        min: Option<Duration>,
+        max: Option<Duration>,
+    ) -> Duration {
+        let min = min.unwrap_or(self.reopen_delay_min);
+        // A pool overriding only one bound may end up with an empty range.
+        let max = max.unwrap_or(self.reopen_delay_max).max(min);
+        let secs = random
+            .lock()
+            .unwrap()
+            .gen_range(min.as_secs()..=max.as_secs());
+        Duration::from_secs(secs)
+    }
+}
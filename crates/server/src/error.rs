@@ -0,0 +1,80 @@
+use std::{any::Any, fmt::Display, time::Duration};
+
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use protocol::error::ErrorBody;
+pub(crate) use protocol::error::ErrorCode;
+use serde::Serialize;
+
+/// Error returned by every endpoint, sent as an [`ErrorBody`].
+#[derive(Debug)]
+pub(crate) struct ApiError {
+    pub code: ErrorCode,
+    pub message: String,
+    pub details: Option<serde_json::Value>,
+    pub retry_after: Option<Duration>,
+}
+
+impl ApiError {
+    pub fn new(code: ErrorCode, message: impl Display) -> Self {
+        Self {
+            code,
+            message: message.to_string(),
+            details: None,
+            retry_after: None,
+        }
+    }
+
+    pub fn with_details(mut self, details: impl Serialize) -> Self {
+        self.details = serde_json::to_value(details).ok();
+        self
+    }
+
+    /// Also sends a `Retry-After` header.
+    pub fn with_retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = Some(retry_after);
+        self
+    }
+
+    /// Logs `err` and hides it from the client, which can't do anything about it.
+    pub fn internal(err: impl Display) -> Self {
+        tracing::error!("Internal error: {}", err);
+        Self::new(ErrorCode::InternalError, "Internal Error")
+    }
+}
+
+impl From<sqlx::Error> for ApiError {
+    fn from(err: sqlx::Error) -> Self {
+        Self::internal(err)
+    }
+}
+
+/// Turns a panicking handler into a 500, for `CatchPanicLayer`, so the panic only
+/// fails its own request.
+pub(crate) fn panic_response(panic: Box<dyn Any + Send + 'static>) -> axum::response::Response {
+    let message = panic
+        .downcast_ref::<String>()
+        .map(String::as_str)
+        .or_else(|| panic.downcast_ref::<&str>().copied())
+        .unwrap_or("unknown panic");
+    ApiError::internal(format_args!("Handler panicked: {}", message)).into_response()
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> axum::response::Response {
+        let status =
+            StatusCode::from_u16(self.code.status()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let body = ErrorBody {
+            code: self.code,
+            message: self.message,
+            details: self.details,
+        };
+        let mut response = (status, Json(body)).into_response();
+        if let Some(retry_after) = self.retry_after {
+            response.headers_mut().insert(
+                http::header::RETRY_AFTER,
+                http::HeaderValue::from(retry_after.as_secs().max(1)),
+            );
+        }
+        response
+    }
+}
@@ -0,0 +1,145 @@
+//! Axum's extractors, rejecting bad requests with an [`ApiError`] like every other error
+//! instead of a plain text body.
+
+use axum::{
+    async_trait,
+    body::{Body, Bytes},
+    extract::{
+        rejection::{
+            BytesRejection, FailedToBufferBody, JsonRejection, PathRejection, QueryRejection,
+        },
+        FromRequest, FromRequestParts,
+    },
+    http::{request::Parts, Request},
+    response::IntoResponse,
+};
+use serde::Serialize;
+
+use crate::error::{ApiError, ErrorCode};
+
+/// [`axum::Json`], as a body to extract or to respond with.
+pub(crate) struct Json<T>(pub T);
+
+#[async_trait]
+impl<T, S, B> FromRequest<S, B> for Json<T>
+where
+    axum::Json<T>: FromRequest<S, B, Rejection = JsonRejection>,
+    S: Send + Sync,
+    B: Send + 'static,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request<B>, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::Json(value) = axum::Json::from_request(req, state).await?;
+        Ok(Self(value))
+    }
+}
+
+impl<T: Serialize> IntoResponse for Json<T> {
+    fn into_response(self) -> axum::response::Response {
+        axum::Json(self.0).into_response()
+    }
+}
+
+/// A [`Json`] body the client may leave out entirely. A body that is sent must still be
+/// valid, it isn't silently replaced by `None`.
+pub(crate) struct OptionalJson<T>(pub Option<T>);
+
+#[async_trait]
+impl<T, S, B> FromRequest<S, B> for OptionalJson<T>
+where
+    Bytes: FromRequest<S, B, Rejection = BytesRejection>,
+    axum::Json<T>: FromRequest<S, Body, Rejection = JsonRejection>,
+    S: Send + Sync,
+    B: Send + 'static,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request<B>, state: &S) -> Result<Self, Self::Rejection> {
+        // Many clients send a content type even without a body, only its length tells.
+        let headers = req.headers().clone();
+        let bytes = Bytes::from_request(req, state).await?;
+        if bytes.is_empty() {
+            return Ok(Self(None));
+        }
+        let mut req = Request::new(Body::from(bytes));
+        *req.headers_mut() = headers;
+        let Json(value) = Json::from_request(req, state).await?;
+        Ok(Self(Some(value)))
+    }
+}
+
+/// [`axum::extract::Path`].
+pub(crate) struct Path<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for Path<T>
+where
+    axum::extract::Path<T>: FromRequestParts<S, Rejection = PathRejection>,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::extract::Path(value) =
+            axum::extract::Path::from_request_parts(parts, state).await?;
+        Ok(Self(value))
+    }
+}
+
+/// [`axum::extract::Query`].
+pub(crate) struct Query<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for Query<T>
+where
+    axum::extract::Query<T>: FromRequestParts<S, Rejection = QueryRejection>,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::extract::Query(value) =
+            axum::extract::Query::from_request_parts(parts, state).await?;
+        Ok(Self(value))
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        match rejection {
+            JsonRejection::BytesRejection(rejection) => rejection.into(),
+            _ => ApiError::new(ErrorCode::InvalidRequest, rejection.body_text()),
+        }
+    }
+}
+
+/// Reading the body only fails because of the client: too large, or cut short.
+impl From<BytesRejection> for ApiError {
+    fn from(rejection: BytesRejection) -> Self {
+        match rejection {
+            BytesRejection::FailedToBufferBody(FailedToBufferBody::LengthLimitError(_)) => {
+                ApiError::new(ErrorCode::PayloadTooLarge, "The request body is too large")
+            }
+            _ => ApiError::new(ErrorCode::InvalidRequest, rejection.body_text()),
+        }
+    }
+}
+
+impl From<PathRejection> for ApiError {
+    fn from(rejection: PathRejection) -> Self {
+        match rejection {
+            PathRejection::FailedToDeserializePathParams(_) => {
+                ApiError::new(ErrorCode::InvalidRequest, rejection.body_text())
+            }
+            // The route and the handler disagree, not the client's fault.
+            _ => ApiError::internal(rejection.body_text()),
+        }
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        ApiError::new(ErrorCode::InvalidRequest, rejection.body_text())
+    }
+}
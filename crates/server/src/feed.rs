@@ -0,0 +1,204 @@
+use std::{collections::VecDeque, convert::Infallible, sync::Arc, time::Duration};
+
+use axum::{
+    http::HeaderMap,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse,
+    },
+    Extension,
+};
+use futures_util::stream;
+use protocol::feed::FeedEvent;
+use serde::{Deserialize, Serialize};
+use shuttle_secrets::SecretStore;
+use sqlx::{PgConnection, PgPool};
+use tokio::sync::broadcast;
+
+use crate::{config, error::ApiError, live::Hub, now_utc};
+
+/// Postgres channel announcing new feed events to every server instance.
+pub(crate) const CHANNEL: &str = "feed_events";
+/// How many stored events are read at once when a client catches up.
+const BACKLOG_PAGE: i64 = 500;
+
+/// How long feed events are kept, from the `FEED_*` secrets.
+pub(crate) struct FeedConfig {
+    /// A client resuming from an older event gets what is left.
+    pub retention: Duration,
+}
+
+impl FeedConfig {
+    pub fn from_secrets(secrets: &SecretStore) -> Self {
+        Self {
+            retention: config::get_secs(
+                secrets,
+                "FEED_RETENTION_SECS",
+                Duration::from_secs(24 * 60 * 60),
+            ),
+        }
+    }
+}
+
+/// Stores the event and announces it once `connection`'s transaction commits.
+///
+/// Always record it in the transaction making the change, like the ledger's
+/// [`PointEvent`](crate::ledger::PointEvent), so the feed only shows what happened.
+/// Recording waits for other transactions recording an event to commit, so ids
+/// are in commit order; record last to keep that wait short.
+pub(crate) async fn record(
+    connection: &mut PgConnection,
+    event: &FeedEvent,
+) -> Result<(), sqlx::Error> {
+    // Released with the transaction.
+    const LOCK_QUERY: &str = "SELECT pg_advisory_xact_lock(hashtext('feed_events'));";
+    const INSERT_QUERY: &str = "INSERT INTO feed_events (kind, data, created_at)
+        VALUES ($1::feed_event_kind, $2::json, $3)
+        RETURNING id, kind::text, data::text;";
+    const NOTIFY_QUERY: &str = "SELECT pg_notify($1, $2);";
+
+    sqlx::query(LOCK_QUERY).execute(&mut *connection).await?;
+    let data = serde_json::to_string(event).expect("Feed events serialize to JSON");
+    let entry: FeedEntry = sqlx::query_as(INSERT_QUERY)
+        .bind(event.kind())
+        .bind(data)
+        .bind(now_utc())
+        .fetch_one(&mut *connection)
+        .await?;
+    let payload = serde_json::to_string(&entry).expect("Feed entries serialize to JSON");
+    sqlx::query(NOTIFY_QUERY)
+        .bind(CHANNEL)
+        .bind(payload)
+        .execute(connection)
+        .await?;
+    Ok(())
+}
+
+/// A stored [`FeedEvent`], as sent to clients.
+#[derive(Serialize, Deserialize, sqlx::FromRow, Clone)]
+pub(crate) struct FeedEntry {
+    id: i64,
+    kind: String,
+    /// The event as JSON.
+    data: String,
+}
+
+impl FeedEntry {
+    fn to_event(&self) -> Event {
+        Event::default()
+            .id(self.id.to_string())
+            .event(&self.kind)
+            .data(&self.data)
+    }
+}
+
+/// Deletes the events older than the retention, returning how many.
+pub(crate) async fn prune(
+    connection: &mut PgConnection,
+    config: &FeedConfig,
+) -> Result<u64, sqlx::Error> {
+    const QUERY: &str = "DELETE FROM feed_events WHERE created_at < $1;";
+
+    let result = sqlx::query(QUERY)
+        .bind(now_utc() - config.retention)
+        .execute(connection)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+/// `GET /feed`: Server-Sent Events of what happens in the game, for overlays and dashboards.
+///
+/// Each event has the [`FeedEvent`] kind as its name and its fields as JSON data.
+/// Starts with new events, or right after the `Last-Event-ID` header, which browsers
+/// send when reconnecting.
+pub(crate) async fn get_feed(
+    headers: HeaderMap,
+    Extension(database): Extension<PgPool>,
+    Extension(hub): Extension<Hub>,
+) -> Result<impl IntoResponse, ApiError> {
+    const LAST_ID_QUERY: &str = "SELECT COALESCE(MAX(id), 0) FROM feed_events;";
+
+    // Subscribed first, so nothing recorded from here on is missed.
+    let receiver = hub.feed();
+    let resume_after = headers
+        .get("last-event-id")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<i64>().ok());
+    let (last_id, catching_up) = match resume_after {
+        Some(last_id) => (last_id, true),
+        None => {
+            let (last_id,): (i64,) = sqlx::query_as(LAST_ID_QUERY).fetch_one(&database).await?;
+            (last_id, false)
+        }
+    };
+    let feed = Feed {
+        database,
+        receiver,
+        last_id,
+        catching_up,
+        backlog: VecDeque::new(),
+    };
+    let events = stream::unfold(feed, |mut feed| async move {
+        let entry = feed.next().await?;
+        Some((Ok::<_, Infallible>(entry.to_event()), feed))
+    });
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+/// One client's position in the feed.
+struct Feed {
+    database: PgPool,
+    receiver: broadcast::Receiver<Option<Arc<FeedEntry>>>,
+    /// Id of the last event sent; the next one sent has a greater id.
+    last_id: i64,
+    /// Reading from the database until it has nothing after `last_id`.
+    catching_up: bool,
+    backlog: VecDeque<FeedEntry>,
+}
+
+impl Feed {
+    /// Waits for the next event, `None` ends the stream and the client reconnects.
+    async fn next(&mut self) -> Option<FeedEntry> {
+        loop {
+            if let Some(entry) = self.backlog.pop_front() {
+                self.last_id = entry.id;
+                return Some(entry);
+            }
+            if self.catching_up {
+                match self.read_backlog().await {
+                    Ok(entries) if entries.is_empty() => self.catching_up = false,
+                    Ok(entries) => self.backlog = entries.into(),
+                    Err(err) => {
+                        tracing::error!("Failed to read the feed: {}", err);
+                        return None;
+                    }
+                }
+                continue;
+            }
+            match self.receiver.recv().await {
+                // Announced before the catch up read it, or before the client subscribed.
+                Ok(Some(entry)) if entry.id <= self.last_id => {}
+                Ok(Some(entry)) => {
+                    self.last_id = entry.id;
+                    return Some(FeedEntry::clone(&entry));
+                }
+                // Announcements were missed, the database has them.
+                Ok(None) | Err(broadcast::error::RecvError::Lagged(_)) => self.catching_up = true,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    async fn read_backlog(&self) -> Result<Vec<FeedEntry>, sqlx::Error> {
+        const QUERY: &str = "SELECT id, kind::text, data::text FROM feed_events
+            WHERE id > $1
+            ORDER BY id
+            LIMIT $2;";
+
+        sqlx::query_as(QUERY)
+            .bind(self.last_id)
+            .bind(BACKLOG_PAGE)
+            .fetch_all(&self.database)
+            .await
+    }
+}
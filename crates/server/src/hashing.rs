@@ -0,0 +1,170 @@
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Algorithm, Argon2, Params, Version,
+};
+use std::{num::NonZeroUsize, sync::Arc, thread};
+
+use shuttle_secrets::SecretStore;
+use tokio::{sync::Semaphore, task::spawn_blocking};
+
+use crate::config;
+
+/// How passwords (and reset codes) are hashed: Argon2id with configurable cost.
+///
+/// Hashes from older schemes, like the PBKDF2 ones from before the switch, still verify;
+/// [`PasswordHashing::needs_rehash`] tells when to replace them with a current one.
+pub(crate) struct PasswordHashing {
+    params: Params,
+    /// Hashes running at once, each holding its memory cost until it is done.
+    permits: Arc<Semaphore>,
+}
+
+#[derive(Debug)]
+pub(crate) struct VerifyError;
+
+impl PasswordHashing {
+    /// Defaults follow the OWASP recommendation of 19 MiB, 2 iterations, 1 lane, with as
+    /// many hashes at once as there are CPUs.
+    pub fn from_secrets(secrets: &SecretStore) -> Self {
+        let params = Params::new(
+            config::get_or(secrets, "PASSWORD_HASH_MEMORY_KIB", 19 * 1024),
+            config::get_or(secrets, "PASSWORD_HASH_ITERATIONS", 2),
+            config::get_or(secrets, "PASSWORD_HASH_PARALLELISM", 1),
+            None,
+        )
+        .unwrap_or_else(|e| panic!("Invalid password hash parameters: {}", e));
+        let concurrency = config::get_or(
+            secrets,
+            "PASSWORD_HASH_CONCURRENCY",
+            thread::available_parallelism().map_or(1, NonZeroUsize::get),
+        );
+        assert!(
+            concurrency > 0,
+            "PASSWORD_HASH_CONCURRENCY must not be zero"
+        );
+        Self {
+            params,
+            permits: Arc::new(Semaphore::new(concurrency)),
+        }
+    }
+
+    fn argon2(&self) -> Argon2<'static> {
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, self.params.clone())
+    }
+
+    /// Runs `work` on the blocking pool once a permit is free.
+    ///
+    /// A hash takes long enough to stall every other request on its worker otherwise, and
+    /// the permits keep a login flood from holding the memory of hundreds of them at once.
+    async fn run_limited<T: Send + 'static>(&self, work: impl FnOnce() -> T + Send + 'static) -> T {
+        let permit = self
+            .permits
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("The hashing semaphore is never closed");
+        // Moved into the task, so a request dropped meanwhile doesn't free it early.
+        spawn_blocking(move || {
+            let result = work();
+            drop(permit);
+            result
+        })
+        .await
+        .expect("Password hashing panicked")
+    }
+
+    /// Hashes `password` to a PHC string (`$argon2id$v=19$m=...`).
+    pub async fn hash(&self, password: impl AsRef<[u8]>) -> String {
+        let argon2 = self.argon2();
+        let password = password.as_ref().to_vec();
+        self.run_limited(move || {
+            let salt = SaltString::generate(&mut OsRng);
+            argon2
+                .hash_password(&password, &salt)
+                .expect("Argon2 hashing with validated parameters")
+                .to_string()
+        })
+        .await
+    }
+
+    /// Checks `password` against a hash from any scheme we have used so far.
+    pub async fn verify(&self, password: impl AsRef<[u8]>, hash: &str) -> Result<(), VerifyError> {
+        let argon2 = self.argon2();
+        let password = password.as_ref().to_vec();
+        let hash = hash.to_string();
+        self.run_limited(move || match PasswordHash::new(&hash) {
+            // The hash carries its own algorithm and parameters.
+            Ok(parsed) if is_argon2(&parsed) => argon2
+                .verify_password(&password, &parsed)
+                .map_err(|_| VerifyError),
+            _ => password_auth::verify_password(password, &hash).map_err(|_| VerifyError),
+        })
+        .await
+    }
+
+    /// Whether `hash` uses another algorithm or other parameters than new hashes do.
+    pub fn needs_rehash(&self, hash: &str) -> bool {
+        let Ok(parsed) = PasswordHash::new(hash) else {
+            return true;
+        };
+        let current = parsed.algorithm == Algorithm::Argon2id.ident()
+            && parsed.version == Some(Version::V0x13.into())
+            && Params::try_from(&parsed).is_ok_and(|params| {
+                params.m_cost() == self.params.m_cost()
+                    && params.t_cost() == self.params.t_cost()
+                    && params.p_cost() == self.params.p_cost()
+            });
+        !current
+    }
+}
+
+fn is_argon2(hash: &PasswordHash) -> bool {
+    [Algorithm::Argon2d, Algorithm::Argon2i, Algorithm::Argon2id]
+        .iter()
+        .any(|algorithm| hash.algorithm == algorithm.ident())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Cheap parameters, the cost doesn't matter here.
+    fn hashing(iterations: u32) -> PasswordHashing {
+        PasswordHashing {
+            params: Params::new(Params::MIN_M_COST, iterations, 1, None).unwrap(),
+            permits: Arc::new(Semaphore::new(1)),
+        }
+    }
+
+    #[tokio::test]
+    async fn current_hash_needs_no_rehash() {
+        let hashing = hashing(1);
+        let hash = hashing.hash("correct horse").await;
+        assert!(!hashing.needs_rehash(&hash));
+        assert!(hashing.verify("correct horse", &hash).await.is_ok());
+        assert!(hashing.verify("wrong horse", &hash).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn other_parameters_need_rehash() {
+        let hash = hashing(1).hash("correct horse").await;
+        assert!(hashing(2).needs_rehash(&hash));
+        // Still verifies, with the parameters stored in the hash.
+        assert!(hashing(2).verify("correct horse", &hash).await.is_ok());
+    }
+
+    #[test]
+    fn other_schemes_need_rehash() {
+        let hashing = hashing(1);
+        let argon2i = Argon2::new(Algorithm::Argon2i, Version::V0x13, hashing.params.clone())
+            .hash_password(b"correct horse", &SaltString::generate(&mut OsRng))
+            .unwrap()
+            .to_string();
+        assert!(hashing.needs_rehash(&argon2i));
+        assert!(hashing.needs_rehash(
+            "$pbkdf2-sha256$i=600000,l=32$c2FsdHNhbHRzYWx0$\
+             2Cp7ZZmJdAiHNNjHgfakV6WMl2AIhNYhMO4Bu5U1hYE"
+        ));
+        assert!(hashing.needs_rehash("not a hash"));
+    }
+}
@@ -0,0 +1,282 @@
+use std::{sync::Arc, time::Duration};
+
+use shuttle_secrets::SecretStore;
+use sqlx::{PgConnection, PgPool};
+use tokio::{sync::watch, task::JoinHandle, time::MissedTickBehavior};
+
+use crate::{
+    authentication::SessionConfig,
+    config,
+    economy::EconomyConfig,
+    feed::{self, FeedConfig},
+    leaderboard, now_utc, pools,
+    throttle::LoginThrottle,
+    Random,
+};
+
+/// How often each background job runs, from the `JOBS_*` secrets.
+pub(crate) struct JobsConfig {
+    pub reopen_pools: Duration,
+    pub clean_sessions: Duration,
+    pub snapshot_leaderboards: Duration,
+    pub prune_feed: Duration,
+    pub prune_login_attempts: Duration,
+}
+
+impl JobsConfig {
+    pub fn from_secrets(secrets: &SecretStore) -> Self {
+        let config = Self {
+            reopen_pools: config::get_secs(
+                secrets,
+                "JOBS_REOPEN_POOLS_SECS",
+                Duration::from_secs(10),
+            ),
+            clean_sessions: config::get_secs(
+                secrets,
+                "JOBS_CLEAN_SESSIONS_SECS",
+                Duration::from_secs(60 * 60),
+            ),
+            snapshot_leaderboards: config::get_secs(
+                secrets,
+                "JOBS_SNAPSHOT_LEADERBOARDS_SECS",
+                Duration::from_secs(60 * 60),
+            ),
+            prune_feed: config::get_secs(
+                secrets,
+                "JOBS_PRUNE_FEED_SECS",
+                Duration::from_secs(60 * 60),
+            ),
+            prune_login_attempts: config::get_secs(
+                secrets,
+                "JOBS_PRUNE_LOGIN_ATTEMPTS_SECS",
+                Duration::from_secs(60 * 60),
+            ),
+        };
+        // A zero period would make the job's interval panic, inside its task.
+        for (period, key) in [
+            (config.reopen_pools, "JOBS_REOPEN_POOLS_SECS"),
+            (config.clean_sessions, "JOBS_CLEAN_SESSIONS_SECS"),
+            (
+                config.snapshot_leaderboards,
+                "JOBS_SNAPSHOT_LEADERBOARDS_SECS",
+            ),
+            (config.prune_feed, "JOBS_PRUNE_FEED_SECS"),
+            (
+                config.prune_login_attempts,
+                "JOBS_PRUNE_LOGIN_ATTEMPTS_SECS",
+            ),
+        ] {
+            assert!(!period.is_zero(), "{} must be positive", key);
+        }
+        config
+    }
+}
+
+/// What the jobs need from the rest of the server.
+pub(crate) struct JobContext {
+    pub database: PgPool,
+    pub random: Random,
+    pub economy: Arc<EconomyConfig>,
+    pub session_config: Arc<SessionConfig>,
+    pub feed_config: Arc<FeedConfig>,
+    pub login_throttle: Arc<LoginThrottle>,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Job {
+    /// Refills empty pools, which reopen after their delay.
+    ReopenPools,
+    /// Deletes expired and idle sessions.
+    CleanSessions,
+    /// Copies the current leaderboards into `leaderboard_snapshots`.
+    SnapshotLeaderboards,
+    /// Deletes feed events past their retention.
+    PruneFeed,
+    /// Deletes failed logins that are forgotten.
+    PruneLoginAttempts,
+}
+
+impl Job {
+    fn name(self) -> &'static str {
+        match self {
+            Job::ReopenPools => "reopen_pools",
+            Job::CleanSessions => "clean_sessions",
+            Job::SnapshotLeaderboards => "snapshot_leaderboards",
+            Job::PruneFeed => "prune_feed",
+            Job::PruneLoginAttempts => "prune_login_attempts",
+        }
+    }
+
+    fn period(self, config: &JobsConfig) -> Duration {
+        match self {
+            Job::ReopenPools => config.reopen_pools,
+            Job::CleanSessions => config.clean_sessions,
+            Job::SnapshotLeaderboards => config.snapshot_leaderboards,
+            Job::PruneFeed => config.prune_feed,
+            Job::PruneLoginAttempts => config.prune_login_attempts,
+        }
+    }
+
+    /// Does the work of one run, returning how many rows it handled.
+    async fn run(
+        self,
+        context: &JobContext,
+        connection: &mut PgConnection,
+    ) -> Result<u64, sqlx::Error> {
+        match self {
+            Job::ReopenPools => {
+                const QUERY: &str =
+                    "SELECT id FROM points_pool WHERE points <= 0 FOR UPDATE SKIP LOCKED;";
+
+                let pool_ids: Vec<(i32,)> =
+                    sqlx::query_as(QUERY).fetch_all(&mut *connection).await?;
+                for (pool_id,) in &pool_ids {
+                    pools::refill(
+                        &mut *connection,
+                        &context.economy,
+                        &context.random,
+                        *pool_id,
+                    )
+                    .await?;
+                }
+                Ok(pool_ids.len() as u64)
+            }
+            Job::CleanSessions => {
+                const QUERY: &str =
+                    "DELETE FROM sessions WHERE expires_at <= $1 OR last_seen_at <= $2;";
+
+                let now = now_utc();
+                let result = sqlx::query(QUERY)
+                    .bind(now)
+                    .bind(now - context.session_config.idle_timeout)
+                    .execute(&mut *connection)
+                    .await?;
+                Ok(result.rows_affected())
+            }
+            Job::SnapshotLeaderboards => {
+                let now = now_utc();
+                let mut rows = 0;
+                for (name, board) in leaderboard::BOARDS {
+                    let query = format!(
+                        "INSERT INTO leaderboard_snapshots (taken_at, board, entity_id, rank, points)
+                        SELECT $1, $2, id, RANK() OVER (ORDER BY points DESC), points
+                        FROM ({}) board;",
+                        board
+                    );
+                    let result = sqlx::query(&query)
+                        .bind(now)
+                        .bind(name)
+                        .execute(&mut *connection)
+                        .await?;
+                    rows += result.rows_affected();
+                }
+                Ok(rows)
+            }
+            Job::PruneFeed => feed::prune(connection, &context.feed_config).await,
+            Job::PruneLoginAttempts => context.login_throttle.prune(connection).await,
+        }
+    }
+
+    /// Runs the job unless another instance is running it or already did this period.
+    async fn run_once(
+        self,
+        context: &JobContext,
+        period: Duration,
+    ) -> Result<Option<u64>, sqlx::Error> {
+        // Released with the transaction, so a crashed instance can't hold it.
+        const LOCK_QUERY: &str = "SELECT pg_try_advisory_xact_lock(hashtext('job:' || $1));";
+        const DUE_QUERY: &str = "INSERT INTO job_runs (name, last_run_at) VALUES ($1, $2)
+            ON CONFLICT (name) DO UPDATE SET last_run_at = $2
+            WHERE job_runs.last_run_at <= $3
+            RETURNING name;";
+
+        let mut transaction = context.database.begin().await?;
+        let (locked,): (bool,) = sqlx::query_as(LOCK_QUERY)
+            .bind(self.name())
+            .fetch_one(&mut *transaction)
+            .await?;
+        if !locked {
+            return Ok(None);
+        }
+        // Some slack, so instances ticking slightly apart don't skip every other period.
+        let now = now_utc();
+        let due: Option<(String,)> = sqlx::query_as(DUE_QUERY)
+            .bind(self.name())
+            .bind(now)
+            .bind(now - period.mul_f32(0.9))
+            .fetch_optional(&mut *transaction)
+            .await?;
+        if due.is_none() {
+            return Ok(None);
+        }
+        let rows = self.run(context, &mut transaction).await?;
+        transaction.commit().await?;
+        Ok(Some(rows))
+    }
+}
+
+/// Handle on the background jobs, see [`Scheduler::stop`].
+pub(crate) struct Scheduler {
+    shutdown: watch::Sender<()>,
+    jobs: Vec<JoinHandle<()>>,
+}
+
+impl Scheduler {
+    /// Starts every job on its own schedule.
+    ///
+    /// Several server instances can run the jobs at once: each run takes a database lock
+    /// and checks `job_runs`, so a job runs at most once per period overall.
+    pub fn start(config: &JobsConfig, context: JobContext) -> Self {
+        let context = Arc::new(context);
+        let (shutdown, shutdown_receiver) = watch::channel(());
+        let mut jobs = Vec::new();
+        for job in [
+            Job::ReopenPools,
+            Job::CleanSessions,
+            Job::SnapshotLeaderboards,
+            Job::PruneFeed,
+            Job::PruneLoginAttempts,
+        ] {
+            jobs.push(tokio::spawn(run_every(
+                job,
+                job.period(config),
+                context.clone(),
+                shutdown_receiver.clone(),
+            )));
+        }
+        Self { shutdown, jobs }
+    }
+
+    /// Stops the jobs, waiting for the runs in progress to finish first.
+    pub async fn stop(self) {
+        drop(self.shutdown);
+        for job in self.jobs {
+            if let Err(err) = job.await {
+                tracing::error!("Job panicked: {}", err);
+            }
+        }
+    }
+}
+
+async fn run_every(
+    job: Job,
+    period: Duration,
+    context: Arc<JobContext>,
+    mut shutdown: watch::Receiver<()>,
+) {
+    let mut interval = tokio::time::interval(period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            // Only errors, when the scheduler is stopped.
+            _ = shutdown.changed() => break,
+        }
+        match job.run_once(&context, period).await {
+            Ok(Some(rows)) if rows > 0 => tracing::info!(job = job.name(), rows, "Job done"),
+            Ok(_) => {}
+            Err(err) => tracing::error!(job = job.name(), "Job failed: {}", err),
+        }
+    }
+    tracing::info!(job = job.name(), "Job stopped");
+}
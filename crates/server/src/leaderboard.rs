@@ -0,0 +1,90 @@
+use axum::{response::IntoResponse, Extension};
+use protocol::leaderboard::{Entry, Leaderboard, Pagination};
+use sqlx::{PgConnection, PgPool};
+
+use crate::{
+    error::{ApiError, ErrorCode},
+    extract::{Json, Path, Query},
+};
+
+const DEFAULT_LIMIT: i64 = 20;
+const MAX_LIMIT: i64 = 100;
+
+/// Champions by their points.
+const CHAMPIONS_BOARD: &str = "SELECT id, name, points::bigint AS points, team_id FROM champions";
+
+/// Teams by the sum of their champions' points.
+const TEAMS_BOARD: &str = "SELECT teams.id, teams.name,
+        COALESCE(SUM(champions.points), 0)::bigint AS points, NULL::int AS team_id
+    FROM teams LEFT JOIN champions ON champions.team_id = teams.id
+    GROUP BY teams.id";
+
+/// Players by the points they gave to champions; those who gave none are left out.
+const PLAYERS_BOARD: &str = "SELECT users.id, users.name,
+        SUM(contributions.points)::bigint AS points, users.team_id
+    FROM users JOIN champion_contributions contributions ON contributions.user_id = users.id
+    GROUP BY users.id
+    HAVING SUM(contributions.points) > 0";
+
+/// Every leaderboard, by the name used in its route.
+pub(crate) const BOARDS: [(&str, &str); 3] = [
+    ("champions", CHAMPIONS_BOARD),
+    ("teams", TEAMS_BOARD),
+    ("players", PLAYERS_BOARD),
+];
+
+/// `GET /leaderboard/:board`, with `?limit=` (at most 100) and `?offset=`.
+pub(crate) async fn get_leaderboard(
+    Extension(database): Extension<PgPool>,
+    Path(board): Path<String>,
+    Query(Pagination { limit, offset }): Query<Pagination>,
+) -> Result<impl IntoResponse, ApiError> {
+    let Some((_, board)) = BOARDS.into_iter().find(|(name, _)| *name == board) else {
+        return Err(ApiError::new(
+            ErrorCode::LeaderboardNotFound,
+            "No such leaderboard, try champions, teams or players.",
+        ));
+    };
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let offset = offset.unwrap_or(0).max(0);
+
+    let count_query = format!("SELECT COUNT(*) FROM ({}) board;", board);
+    // One snapshot for both queries, so the total matches the entries.
+    let mut transaction = database.begin().await?;
+    sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ READ ONLY;")
+        .execute(&mut *transaction)
+        .await?;
+    let entries = ranked_entries(&mut transaction, board, limit, offset).await?;
+    let (total,): (i64,) = sqlx::query_as(&count_query)
+        .fetch_one(&mut *transaction)
+        .await?;
+    transaction.commit().await?;
+
+    Ok(Json(Leaderboard {
+        total,
+        limit,
+        offset,
+        entries,
+    }))
+}
+
+/// One page of `board`, which is one of the [`BOARDS`] queries.
+pub(crate) async fn ranked_entries(
+    connection: &mut PgConnection,
+    board: &str,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<Entry>, sqlx::Error> {
+    let query = format!(
+        "SELECT RANK() OVER (ORDER BY points DESC) AS rank, id, name, points, team_id
+        FROM ({}) board
+        ORDER BY rank, name
+        LIMIT $1 OFFSET $2;",
+        board
+    );
+    sqlx::query_as(&query)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(connection)
+        .await
+}
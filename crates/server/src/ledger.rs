@@ -0,0 +1,86 @@
+use sqlx::PgConnection;
+
+/// A change to points balances, appended to the `point_events` ledger.
+///
+/// Always record it in the transaction making the change, so the ledger and the
+/// balances can't disagree.
+pub(crate) enum PointEvent {
+    /// `user_id` took a point out of a pool.
+    Collect { user_id: i32, pool_id: i32 },
+    /// `user_id` gave `amount` of their points to a champion.
+    Assign {
+        user_id: i32,
+        champion_id: i32,
+        amount: i32,
+    },
+    /// The server put `amount` points back in a pool.
+    Refill { pool_id: i32, amount: i32 },
+    /// Admin `user_id` changed the points of a pool by `amount`, negative when lowered.
+    Adjust {
+        user_id: i32,
+        pool_id: i32,
+        amount: i32,
+    },
+}
+
+#[derive(sqlx::Type)]
+#[sqlx(type_name = "point_event_kind", rename_all = "lowercase")]
+enum PointEventKind {
+    Collect,
+    Assign,
+    Refill,
+    Adjust,
+}
+
+impl PointEvent {
+    pub async fn record(self, connection: &mut PgConnection) -> Result<(), sqlx::Error> {
+        const QUERY: &str =
+            "INSERT INTO point_events (kind, user_id, champion_id, pool_id, amount, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6);";
+
+        let (kind, user_id, champion_id, pool_id, amount) = match self {
+            PointEvent::Collect { user_id, pool_id } => (
+                PointEventKind::Collect,
+                Some(user_id),
+                None,
+                Some(pool_id),
+                1,
+            ),
+            PointEvent::Assign {
+                user_id,
+                champion_id,
+                amount,
+            } => (
+                PointEventKind::Assign,
+                Some(user_id),
+                Some(champion_id),
+                None,
+                amount,
+            ),
+            PointEvent::Refill { pool_id, amount } => {
+                (PointEventKind::Refill, None, None, Some(pool_id), amount)
+            }
+            PointEvent::Adjust {
+                user_id,
+                pool_id,
+                amount,
+            } => (
+                PointEventKind::Adjust,
+                Some(user_id),
+                None,
+                Some(pool_id),
+                amount,
+            ),
+        };
+        sqlx::query(QUERY)
+            .bind(kind)
+            .bind(user_id)
+            .bind(champion_id)
+            .bind(pool_id)
+            .bind(amount)
+            .bind(crate::now_utc())
+            .execute(connection)
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,292 @@
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    extract::{
+        ws::{close_code, CloseFrame, Message, WebSocket},
+        WebSocketUpgrade,
+    },
+    response::IntoResponse,
+    Extension,
+};
+use protocol::live::{GameEvent, WsParams};
+use sqlx::{postgres::PgListener, PgConnection, PgPool};
+use tokio::{
+    sync::broadcast,
+    time::{interval_at, Instant},
+};
+
+use crate::{
+    authentication::{AuthState, SessionConfig, SessionToken, Unauthorized},
+    error::ApiError,
+    extract::Query,
+    feed::{self, FeedEntry},
+    leaderboard, pools,
+};
+
+/// Postgres channel the events go through, so every server instance sees them all.
+const CHANNEL: &str = "game_events";
+/// How many entries of each leaderboard are pushed.
+const LEADERBOARD_SIZE: i64 = 10;
+/// Leaderboards are pushed at most this often, not on every assignment.
+const LEADERBOARD_INTERVAL: Duration = Duration::from_secs(2);
+/// How often an open socket checks that its session wasn't revoked or expired.
+const SESSION_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Sends the event to every instance once `connection`'s transaction commits,
+/// and never if it rolls back.
+pub(crate) async fn publish(
+    connection: &mut PgConnection,
+    event: &GameEvent,
+) -> Result<(), sqlx::Error> {
+    const QUERY: &str = "SELECT pg_notify($1, $2);";
+
+    let payload = serde_json::to_string(event).expect("Game events serialize to JSON");
+    sqlx::query(QUERY)
+        .bind(CHANNEL)
+        .bind(payload)
+        .execute(connection)
+        .await?;
+    Ok(())
+}
+
+/// Fans the game events out to the WebSocket connections of this instance, and the
+/// feed events to its `/feed` streams.
+#[derive(Clone)]
+pub(crate) struct Hub {
+    /// Serialized [`GameEvent`]s, `None` when events may have been missed and clients
+    /// must be sent the whole state again.
+    sender: broadcast::Sender<Option<Arc<str>>>,
+    /// `None` when events may have been missed, see [`Hub::feed`].
+    feed: broadcast::Sender<Option<Arc<FeedEntry>>>,
+}
+
+impl Hub {
+    /// Starts listening to the events of every instance.
+    pub fn start(database: PgPool) -> Self {
+        let (sender, _) = broadcast::channel(256);
+        let (feed, _) = broadcast::channel(256);
+        let hub = Self { sender, feed };
+        tokio::spawn(listen(database, hub.clone()));
+        hub
+    }
+
+    /// Feed events recorded from now on. A `None` means some were missed while the
+    /// connection to the database was down, and must be read from `feed_events`.
+    pub fn feed(&self) -> broadcast::Receiver<Option<Arc<FeedEntry>>> {
+        self.feed.subscribe()
+    }
+}
+
+async fn listen(database: PgPool, hub: Hub) {
+    let mut leaderboards_changed = false;
+    let mut interval = tokio::time::interval(LEADERBOARD_INTERVAL);
+    loop {
+        let mut listener = connect_listener(&database).await;
+        // Events sent while not listening are lost, clients reload the state instead.
+        // Fails only when nobody is connected.
+        let _ = hub.sender.send(None);
+        let _ = hub.feed.send(None);
+        loop {
+            tokio::select! {
+                // `recv` would reconnect silently; `try_recv` tells when events were lost.
+                notification = listener.try_recv() => match notification {
+                    Ok(Some(notification)) if notification.channel() == feed::CHANNEL => {
+                        match serde_json::from_str(notification.payload()) {
+                            Ok(entry) => { let _ = hub.feed.send(Some(Arc::new(entry))); }
+                            Err(err) => tracing::error!("Invalid feed event: {}", err),
+                        }
+                    }
+                    Ok(Some(notification)) => {
+                        match serde_json::from_str::<GameEvent>(notification.payload()) {
+                            Ok(event) => {
+                                leaderboards_changed |=
+                                    matches!(event, GameEvent::ChampionPoints { .. });
+                                let _ = hub.sender.send(Some(notification.payload().into()));
+                            }
+                            Err(err) => tracing::error!("Invalid game event: {}", err),
+                        }
+                    }
+                    Ok(None) => {
+                        tracing::warn!("Lost the game events connection");
+                        break;
+                    }
+                    Err(err) => {
+                        tracing::warn!("Lost the game events connection: {}", err);
+                        break;
+                    }
+                },
+                _ = interval.tick(), if leaderboards_changed => {
+                    leaderboards_changed = false;
+                    match leaderboard_events(&database).await {
+                        Ok(events) => {
+                            for event in events {
+                                let _ = hub.sender.send(Some(event.into()));
+                            }
+                        }
+                        Err(err) => tracing::error!("Failed to load leaderboards: {}", err),
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Listens to both channels, retrying until the database is reachable.
+async fn connect_listener(database: &PgPool) -> PgListener {
+    loop {
+        let listener = async {
+            let mut listener = PgListener::connect_with(database).await?;
+            listener.listen_all([CHANNEL, feed::CHANNEL]).await?;
+            Ok::<_, sqlx::Error>(listener)
+        };
+        match listener.await {
+            Ok(listener) => return listener,
+            Err(err) => {
+                tracing::error!("Failed to listen to game events: {}", err);
+                tokio::time::sleep(Duration::from_secs(5)).await;
+            }
+        }
+    }
+}
+
+/// Top of every leaderboard, serialized.
+async fn leaderboard_events(database: &PgPool) -> Result<Vec<String>, sqlx::Error> {
+    let mut connection = database.acquire().await?;
+    let mut events = Vec::new();
+    for (board, query) in leaderboard::BOARDS {
+        let entries =
+            leaderboard::ranked_entries(&mut connection, query, LEADERBOARD_SIZE, 0).await?;
+        let event = GameEvent::Leaderboard {
+            board: board.to_string(),
+            entries,
+        };
+        events.push(serde_json::to_string(&event).expect("Game events serialize to JSON"));
+    }
+    Ok(events)
+}
+
+/// Current state, sent on connection so clients start in sync, and again whenever
+/// they may have missed events.
+async fn initial_events(database: &PgPool) -> Result<Vec<String>, sqlx::Error> {
+    const CHAMPIONS_QUERY: &str = "SELECT id, points FROM champions ORDER BY id;";
+
+    let mut connection = database.acquire().await?;
+    let champions: Vec<(i32, i32)> = sqlx::query_as(CHAMPIONS_QUERY)
+        .fetch_all(&mut *connection)
+        .await?;
+    let mut events: Vec<String> = champions
+        .into_iter()
+        .map(|(champion_id, points)| GameEvent::ChampionPoints {
+            champion_id,
+            points,
+        })
+        .chain(
+            pools::all_statuses(&mut connection)
+                .await?
+                .into_iter()
+                .map(GameEvent::Pool),
+        )
+        .map(|event| serde_json::to_string(&event).expect("Game events serialize to JSON"))
+        .collect();
+    events.extend(leaderboard_events(database).await?);
+    Ok(events)
+}
+
+/// `GET /ws`: pushes [`GameEvent`]s to a logged in client.
+///
+/// The session token goes in the usual `Authorization` header or cookie, or in `?token=`.
+pub(crate) async fn get_ws(
+    ws: WebSocketUpgrade,
+    mut auth_state: AuthState,
+    Query(WsParams { token }): Query<WsParams>,
+    Extension(database): Extension<PgPool>,
+    Extension(session_config): Extension<Arc<SessionConfig>>,
+    Extension(hub): Extension<Hub>,
+) -> Result<impl IntoResponse, ApiError> {
+    if auth_state.token().is_none() {
+        if let Some(token) = token.and_then(|token| token.parse::<SessionToken>().ok()) {
+            auth_state = AuthState::from_token(token, database.clone(), session_config.clone());
+        }
+    }
+    let user = auth_state.get_user().await?.cloned().ok_or(Unauthorized)?;
+    let token = auth_state.token().ok_or(Unauthorized)?;
+    // Subscribed before loading the state, so an event committed in between is sent
+    // after it. Events carry absolute values, so one already in the state is harmless.
+    let events = hub.sender.subscribe();
+    let initial_events = initial_events(&database).await?;
+    Ok(ws.on_upgrade(move |socket| async move {
+        tracing::info!(name = %user.name, "Live updates connected");
+        serve(
+            socket,
+            database,
+            token,
+            session_config,
+            initial_events,
+            events,
+        )
+        .await;
+        tracing::info!(name = %user.name, "Live updates disconnected");
+    }))
+}
+
+/// Streams `pending` then every event, until the client leaves or its session ends.
+async fn serve(
+    mut socket: WebSocket,
+    database: PgPool,
+    token: SessionToken,
+    session_config: Arc<SessionConfig>,
+    mut pending: Vec<String>,
+    mut events: broadcast::Receiver<Option<Arc<str>>>,
+) {
+    let mut session_check = interval_at(
+        Instant::now() + SESSION_CHECK_INTERVAL,
+        SESSION_CHECK_INTERVAL,
+    );
+    loop {
+        for event in pending.drain(..) {
+            if socket.send(Message::Text(event)).await.is_err() {
+                return;
+            }
+        }
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(Some(event)) => pending.push(event.to_string()),
+                // Events were missed, by this client or by the whole instance.
+                Ok(None) | Err(broadcast::error::RecvError::Lagged(_)) => {
+                    match initial_events(&database).await {
+                        Ok(events) => pending = events,
+                        Err(err) => {
+                            tracing::error!("Failed to reload the live state: {}", err);
+                            return;
+                        }
+                    }
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            },
+            // Logged out, revoked or expired since the upgrade.
+            _ = session_check.tick() => {
+                // A fresh state each time, the user is cached once loaded.
+                let mut session =
+                    AuthState::from_token(token, database.clone(), session_config.clone());
+                match session.get_user().await {
+                    Ok(Some(_)) => {}
+                    Ok(None) => {
+                        let close = CloseFrame {
+                            code: close_code::POLICY,
+                            reason: "Session ended".into(),
+                        };
+                        let _ = socket.send(Message::Close(Some(close))).await;
+                        return;
+                    }
+                    // Checked again next time, a database hiccup shouldn't drop everyone.
+                    Err(err) => tracing::warn!("Failed to check a live session: {}", err),
+                }
+            }
+            // Clients don't send anything we need; pings are answered by axum.
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
@@ -1,4 +1,20 @@
 mod authentication;
+mod config;
+mod economy;
+mod error;
+mod extract;
+mod feed;
+mod hashing;
+mod jobs;
+mod leaderboard;
+mod ledger;
+mod live;
+mod password;
+mod pools;
+mod service;
+mod teams;
+mod throttle;
+mod users;
 
 use std::{
     fmt::Display,
@@ -7,234 +23,383 @@ use std::{
     time::{Duration, Instant},
 };
 
-use authentication::AuthState;
+use authentication::{RequireUser, SessionConfig};
 use axum::{
-    extract::{Path, State},
+    extract::State,
     http::StatusCode,
     middleware,
     response::IntoResponse,
-    routing::{get, post},
+    routing::{delete, get, post, put},
     Extension, Router,
 };
+use error::{ApiError, ErrorCode};
+use extract::{Json, OptionalJson, Path};
+use ledger::PointEvent;
+use protocol::{
+    feed::FeedEvent,
+    live::GameEvent,
+    points::{AssignData, AssignResult, GLOBAL_POOL},
+    routes,
+    teams::{Champion, Team},
+};
 use rand::{thread_rng, Rng};
 use rand_chacha::ChaCha8Rng;
 use rand_core::{OsRng, RngCore, SeedableRng};
-use serde::{Deserialize, Serialize};
+use shuttle_secrets::SecretStore;
 use sqlx::{postgres::types::PgInterval, PgPool};
-use time::{OffsetDateTime, PrimitiveDateTime};
-use tokio::time::interval;
+use throttle::LoginThrottle;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime, PrimitiveDateTime};
+use tower_http::catch_panic::CatchPanicLayer;
 
 type Random = Arc<Mutex<ChaCha8Rng>>;
 
 #[shuttle_runtime::main]
 async fn axum(
     #[shuttle_shared_db::Postgres(local_uri = "{secrets.DATABASE_URL}")] pool: PgPool,
-) -> shuttle_axum::ShuttleAxum {
+    #[shuttle_secrets::Secrets] secrets: SecretStore,
+) -> Result<service::GameService, shuttle_runtime::Error> {
     sqlx::migrate!()
         .run(&pool)
         .await
         .expect("Migrations failed :(");
+    if let Some(name) = secrets.get("BOOTSTRAP_ADMIN") {
+        authentication::bootstrap_admin(&pool, &name)
+            .await
+            .expect("Admin bootstrap failed");
+    }
 
-    let random = ChaCha8Rng::seed_from_u64(OsRng.next_u64());
+    let random: Random = Arc::new(Mutex::new(ChaCha8Rng::seed_from_u64(OsRng.next_u64())));
+    let session_config = Arc::new(SessionConfig::from_secrets(&secrets));
+    let login_throttle = Arc::new(LoginThrottle::from_secrets(&secrets));
+    let reset_code_config = Arc::new(password::ResetCodeConfig::from_secrets(&secrets));
+    let password_policy = Arc::new(password::PasswordPolicy::from_secrets(&secrets));
+    let economy_config = Arc::new(economy::EconomyConfig::from_secrets(&secrets));
+    let team_config = Arc::new(teams::TeamConfig::from_secrets(&secrets));
+    let password_hashing = Arc::new(hashing::PasswordHashing::from_secrets(&secrets));
+    let feed_config = Arc::new(feed::FeedConfig::from_secrets(&secrets));
+    let scheduler = jobs::Scheduler::start(
+        &jobs::JobsConfig::from_secrets(&secrets),
+        jobs::JobContext {
+            database: pool.clone(),
+            random: random.clone(),
+            economy: economy_config.clone(),
+            session_config: session_config.clone(),
+            feed_config,
+            login_throttle: login_throttle.clone(),
+        },
+    );
+    let hub = live::Hub::start(pool.clone());
     let middleware_database = pool.clone();
+    let middleware_session_config = session_config.clone();
     let router = Router::new()
-        .route("/points/collect", post(points_collect))
-        .route("/champions", get(get_champions))
-        .route("/teams", get(get_teams))
-        .route("/points/assign/:id", post(points_assign))
-        .route("/users/signup", post(authentication::post_signup))
-        .route("/users/login", post(authentication::post_login))
+        .route(routes::COLLECT, post(points_collect))
+        .route(routes::POOL, get(pools::get_global_pool))
+        .route(routes::POOLS, get(pools::get_pools))
+        .route(routes::NAMED_POOL, get(pools::get_pool))
+        .route(routes::NAMED_POOL_COLLECT, post(points_collect_from))
+        .route(routes::ADMIN_POOL, put(pools::put_pool))
+        .route(routes::CHAMPIONS, get(get_champions))
+        .route(routes::TEAMS, get(get_teams))
+        .route(routes::LEADERBOARD, get(leaderboard::get_leaderboard))
+        .route(routes::TEAM_JOIN, post(teams::post_join_team))
+        .route(routes::TEAM_LEAVE, post(teams::post_leave_team))
+        .route(routes::ASSIGN, post(points_assign))
+        .route(routes::ME, get(users::get_me))
+        .route(routes::WS, get(live::get_ws))
+        .route(routes::FEED, get(feed::get_feed))
+        .route(routes::SIGNUP, post(authentication::post_signup))
+        .route(routes::LOGIN, post(authentication::post_login))
+        .route(routes::LOGOUT, post(authentication::post_logout))
+        .route(routes::SESSIONS, get(authentication::get_sessions))
+        .route(routes::SESSION, delete(authentication::delete_session))
+        .route(routes::PASSWORD, post(password::post_change_password))
+        .route(routes::PASSWORD_RESET, post(password::post_reset_password))
+        .route(routes::ADMIN_RESET_CODE, post(password::post_reset_code))
+        .route(routes::ADMIN_USER_ROLE, put(authentication::put_user_role))
         .layer(middleware::from_fn(move |req, next| {
-            authentication::auth(req, next, middleware_database.clone())
+            authentication::auth(
+                req,
+                next,
+                middleware_database.clone(),
+                middleware_session_config.clone(),
+            )
         }))
+        .layer(Extension(session_config))
+        .layer(Extension(login_throttle))
+        .layer(Extension(reset_code_config))
+        .layer(Extension(password_policy))
+        .layer(Extension(password_hashing))
+        .layer(Extension(team_config))
+        .layer(Extension(economy_config))
+        .layer(Extension(hub))
         .layer(Extension(pool))
-        .layer(Extension(Arc::new(Mutex::new(random))));
-    Ok(router.into())
+        .layer(Extension(random))
+        .layer(CatchPanicLayer::custom(error::panic_response));
+    Ok(service::GameService { router, scheduler })
 }
 
+/// Current time in UTC, as stored in our `timestamp` columns.
+fn now_utc() -> PrimitiveDateTime {
+    let now = OffsetDateTime::now_utc();
+    PrimitiveDateTime::new(now.date(), now.time())
+}
+
+/// Collects from the global pool, for clients that don't name one.
 async fn points_collect(
-    Extension(mut current_user): Extension<AuthState>,
-    Extension(random): Extension<Random>,
+    user: RequireUser,
+    database: Extension<PgPool>,
+    economy: Extension<Arc<economy::EconomyConfig>>,
+) -> Result<impl IntoResponse, ApiError> {
+    collect(user, database, economy, GLOBAL_POOL).await
+}
+
+async fn points_collect_from(
+    user: RequireUser,
+    database: Extension<PgPool>,
+    economy: Extension<Arc<economy::EconomyConfig>>,
+    Path(pool_name): Path<String>,
+) -> Result<impl IntoResponse, ApiError> {
+    collect(user, database, economy, &pool_name).await
+}
+
+async fn collect(
+    RequireUser(user, _): RequireUser,
     Extension(database): Extension<PgPool>,
-) -> impl IntoResponse {
-    let Some(user) = current_user.get_user().await else {
-        return Err((
-            StatusCode::UNAUTHORIZED,
-            "You must be logged in.".to_string(),
-        ));
-    };
-    let mut transaction = database
-        .begin()
-        .await
-        .map_err(|e| (StatusCode::IM_A_TEAPOT, e.to_string()))?;
-    let row = match sqlx::query!(
-        r#"select can_get_points_time
+    Extension(economy): Extension<Arc<economy::EconomyConfig>>,
+    pool_name: &str,
+) -> Result<impl IntoResponse, ApiError> {
+    let mut transaction = database.begin().await?;
+    let row = sqlx::query!(
+        r#"select can_get_points_time, team_id
         from users
         WHERE id = $1"#,
         user.id
     )
     .fetch_one(&mut *transaction)
-    .await
-    {
-        Ok(todo) => Ok(todo),
-        Err(e) => Err((StatusCode::LOCKED, e.to_string())),
-    }?;
-    let now = OffsetDateTime::now_utc();
-    let now_pdt = PrimitiveDateTime::new(now.date(), now.time());
+    .await?;
+    let now_pdt = now_utc();
     if now_pdt < row.can_get_points_time {
-        return Err((
-            StatusCode::FORBIDDEN,
+        let next_collect_at = row.can_get_points_time.assume_utc();
+        return Err(ApiError::new(
+            ErrorCode::CollectCooldown,
             format!(
                 "You're not ready to collect yet. next time is: {}",
-                row.can_get_points_time
+                next_collect_at
             ),
+        )
+        .with_details(serde_json::json!({
+            "next_collect_at": next_collect_at.format(&Rfc3339).ok(),
+        }))
+        .with_retry_after(
+            (row.can_get_points_time - now_pdt)
+                .try_into()
+                .unwrap_or_default(),
         ));
     }
-    // TODO: that's duplicated, we need to remove from pool.
-    match sqlx::query!(
+    let pool = sqlx::query!(
+        "SELECT id, points, open_at, team_id
+        FROM points_pool
+        WHERE name = $1
+        FOR UPDATE",
+        pool_name
+    )
+    .fetch_optional(&mut *transaction)
+    .await?
+    .ok_or_else(pools::pool_not_found)?;
+    if pool.team_id.is_some() && pool.team_id != row.team_id {
+        return Err(ApiError::new(
+            ErrorCode::WrongTeam,
+            "This pool is for another team.",
+        ));
+    }
+    if now_pdt < pool.open_at {
+        let open_at = pool.open_at.assume_utc();
+        return Err(
+            ApiError::new(ErrorCode::PoolUnavailable, "The pool is not open yet.")
+                .with_details(serde_json::json!({ "open_at": open_at.format(&Rfc3339).ok() }))
+                .with_retry_after((pool.open_at - now_pdt).try_into().unwrap_or_default()),
+        );
+    }
+    if pool.points <= 0 {
+        return Err(ApiError::new(
+            ErrorCode::PoolUnavailable,
+            "No points left in the pool.",
+        ));
+    }
+    let remaining = sqlx::query!(
         "UPDATE points_pool
         SET points = points - 1
-        WHERE open_at < $1
+        WHERE id = $1
         RETURNING points",
-        now_pdt
+        pool.id
     )
     .fetch_one(&mut *transaction)
-    .await
-    {
-        Err(e) => {
-            return Err((
-                StatusCode::FORBIDDEN,
-                format!(
-                    "Probably no points left in pool, or pool not open. ; error: {}",
-                    e
-                ),
-            ))
-        }
-        Ok(row) => {
-            if row.points <= 0 {
-                //let mut random = random.lock().unwrap();
-                let delay = 2;
-                // random.gen_range(2..=6);
-                match sqlx::query!(
-                    "UPDATE points_pool
-                    SET points = 200,
-                    open_at = $1
-                    ",
-                    now_pdt + std::time::Duration::from_secs(delay * 60 * 60)
-                )
-                .execute(&mut *transaction)
-                .await
-                {
-                    Err(e) => {
-                        return Err((
-                            StatusCode::FORBIDDEN,
-                            format!(
-                                "Probably no points left in pool, or pool is not open. ; error: {}",
-                                e
-                            ),
-                        ))
-                    }
-                    Ok(row) => Ok(()),
-                }?;
-            }
-        }
-    };
-
-    let row = match sqlx::query!(
+    .await?;
+    if remaining.points <= 0 {
+        let pool = pools::status(&mut transaction, pool.id).await?;
+        live::publish(&mut transaction, &GameEvent::Pool(pool)).await?;
+    }
+    // Once empty, the pool is refilled by the scheduler, see `jobs::Job::ReopenPools`.
+    let row = sqlx::query!(
         "UPDATE users
         SET points = points + 1,
-            can_get_points_time = NOW() + CAST('12 seconds' AS INTERVAL)
+            can_get_points_time = $2
         WHERE id = $1
         RETURNING points",
-        dbg!(user.id)
+        user.id,
+        now_pdt + economy.collect_cooldown
     )
     .fetch_one(&mut *transaction)
-    .await
-    {
-        Ok(todo) => Ok(todo),
-        Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
-    }?;
+    .await?;
+    PointEvent::Collect {
+        user_id: user.id,
+        pool_id: pool.id,
+    }
+    .record(&mut transaction)
+    .await?;
+    feed::record(
+        &mut transaction,
+        &FeedEvent::Collect {
+            user_id: user.id,
+            name: user.name.clone(),
+            pool_id: pool.id,
+            pool: pool_name.to_string(),
+        },
+    )
+    .await?;
 
-    transaction
-        .commit()
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-    Ok((StatusCode::OK, axum::Json(row.points)))
+    transaction.commit().await?;
+    Ok((StatusCode::OK, Json(row.points)))
 }
 
 async fn points_assign(
-    Extension(mut current_user): Extension<AuthState>,
+    RequireUser(user, _): RequireUser,
     Extension(database): Extension<PgPool>,
+    Extension(team_config): Extension<Arc<teams::TeamConfig>>,
     Path(champion_id): Path<i32>,
-) -> impl IntoResponse {
-    let Some(user) = current_user.get_user().await else {
-        return Err((
-            StatusCode::UNAUTHORIZED,
-            "You must be logged in.".to_string(),
+    OptionalJson(body): OptionalJson<AssignData>,
+) -> Result<impl IntoResponse, ApiError> {
+    let AssignData { amount } = body.unwrap_or_default();
+    if amount <= 0 {
+        return Err(ApiError::new(
+            ErrorCode::InvalidAmount,
+            "The amount must be a positive number.",
         ));
-    };
-    let mut transaction = database
-        .begin()
-        .await
-        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
-    match sqlx::query!(
+    }
+    let mut transaction = database.begin().await?;
+    // Checked before touching any balance, and locked so it can't vanish before the credit.
+    let champion = sqlx::query!(
+        "SELECT team_id FROM champions WHERE id = $1 FOR UPDATE",
+        champion_id
+    )
+    .fetch_optional(&mut *transaction)
+    .await?
+    .ok_or_else(|| ApiError::new(ErrorCode::ChampionNotFound, "No such champion."))?;
+    if team_config.assign_own_team_only {
+        // Locks the user row, so they can't switch team until the points are in.
+        let row = sqlx::query!(
+            "SELECT team_id FROM users WHERE id = $1 FOR UPDATE",
+            user.id
+        )
+        .fetch_one(&mut *transaction)
+        .await?;
+        if row.team_id != Some(champion.team_id) {
+            return Err(ApiError::new(
+                ErrorCode::WrongTeam,
+                "You can only give points to the champions of your team.",
+            ));
+        }
+    }
+    // Returning early from here on drops the transaction, which rolls back the debit.
+    let debited = sqlx::query!(
         "UPDATE users
-        SET points = points - 1
-        WHERE id = $1",
-        user.id
+        SET points = points - $2
+        WHERE id = $1 AND points >= $2
+        RETURNING points",
+        user.id,
+        amount
     )
-    .fetch_one(&mut *transaction)
-    .await
-    {
-        Ok(_) => {}
-        Err(e) => return Err((StatusCode::BAD_REQUEST, e.to_string())),
+    .fetch_optional(&mut *transaction)
+    .await?;
+    let Some(debited) = debited else {
+        let row = sqlx::query!("SELECT points FROM users WHERE id = $1", user.id)
+            .fetch_one(&mut *transaction)
+            .await?;
+        return Err(ApiError::new(
+            ErrorCode::InsufficientPoints,
+            "You don't have enough points.",
+        )
+        .with_details(serde_json::json!({ "points": row.points, "amount": amount })));
     };
-    let row = match sqlx::query!(
+    let credited = sqlx::query!(
         "UPDATE champions
-        SET points = points + 1
+        SET points = points + $2
         WHERE id = $1
         RETURNING points",
         champion_id,
+        amount
     )
     .fetch_one(&mut *transaction)
-    .await
-    {
-        Ok(row) => Ok(row),
-        Err(e) => Err((StatusCode::BAD_REQUEST, e.to_string())),
-    }?;
-    transaction
-        .commit()
-        .await
-        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
-    Ok((StatusCode::OK, axum::Json(row.points)))
+    .await?;
+    sqlx::query!(
+        "INSERT INTO champion_contributions (user_id, champion_id, points)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (user_id, champion_id) DO UPDATE
+        SET points = champion_contributions.points + $3",
+        user.id,
+        champion_id,
+        amount
+    )
+    .execute(&mut *transaction)
+    .await?;
+    PointEvent::Assign {
+        user_id: user.id,
+        champion_id,
+        amount,
+    }
+    .record(&mut transaction)
+    .await?;
+    live::publish(
+        &mut transaction,
+        &GameEvent::ChampionPoints {
+            champion_id,
+            points: credited.points,
+        },
+    )
+    .await?;
+    feed::record(
+        &mut transaction,
+        &FeedEvent::Assign {
+            user_id: user.id,
+            name: user.name,
+            champion_id,
+            amount,
+            champion_points: credited.points,
+        },
+    )
+    .await?;
+    transaction.commit().await?;
+    Ok((
+        StatusCode::OK,
+        Json(AssignResult {
+            champion_id,
+            champion_points: credited.points,
+            points: debited.points,
+        }),
+    ))
 }
 
-#[derive(Serialize)]
-struct Champion {
-    id: i64,
-    team_id: i64,
-    name: String,
-}
-async fn get_champions(Extension(database): Extension<PgPool>) -> impl IntoResponse {
-    match sqlx::query_as!(Champion, "SELECT id, team_id, name from champions")
+async fn get_champions(
+    Extension(database): Extension<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    let rows = sqlx::query_as!(Champion, "SELECT id, team_id, name from champions")
         .fetch_all(&database)
-        .await
-    {
-        Ok(rows) => Ok((StatusCode::OK, axum::Json(rows))),
-        Err(e) => Err((StatusCode::BAD_REQUEST, e.to_string())),
-    }
+        .await?;
+    Ok((StatusCode::OK, Json(rows)))
 }
-#[derive(Serialize)]
-struct Team {
-    id: i64,
-    name: String,
-}
-async fn get_teams(Extension(database): Extension<PgPool>) -> impl IntoResponse {
-    match sqlx::query_as!(Team, "SELECT id, name from teams")
+async fn get_teams(Extension(database): Extension<PgPool>) -> Result<impl IntoResponse, ApiError> {
+    let rows = sqlx::query_as!(Team, "SELECT id, name from teams")
         .fetch_all(&database)
-        .await
-    {
-        Ok(rows) => Ok((StatusCode::OK, axum::Json(rows))),
-        Err(e) => Err((StatusCode::BAD_REQUEST, e.to_string())),
-    }
+        .await?;
+    Ok((StatusCode::OK, Json(rows)))
 }
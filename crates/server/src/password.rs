@@ -0,0 +1,505 @@
+use std::{collections::HashSet, error::Error, fmt::Display, sync::Arc, time::Duration};
+
+use axum::{
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+    Extension,
+};
+use protocol::password::{PasswordChangeData, PasswordResetData, PolicyViolation, ResetCodeData};
+use rand::Rng;
+use shuttle_secrets::SecretStore;
+use sqlx::PgPool;
+
+use crate::{
+    authentication::{RequireAdmin, RequireUser},
+    config,
+    error::{ApiError, ErrorCode},
+    extract::{Json, Path},
+    hashing::PasswordHashing,
+    now_utc,
+    throttle::{client_ip, AttemptKey, LoginThrottle, MAX_NAME_LENGTH},
+    Random,
+};
+
+#[derive(Debug)]
+pub(crate) enum PasswordError {
+    MissingDetails,
+    WrongPassword,
+    InvalidPassword(PolicyViolation),
+    UnknownUser,
+    InvalidCode,
+    TooManyAttempts(Duration),
+    InternalError,
+}
+
+impl Display for PasswordError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PasswordError::MissingDetails => f.write_str("Missing details"),
+            PasswordError::WrongPassword => f.write_str("Wrong password"),
+            PasswordError::InvalidPassword(reason) => write!(f, "Invalid Password: {}", reason),
+            PasswordError::UnknownUser => f.write_str("User does not exist"),
+            PasswordError::InvalidCode => f.write_str("Invalid or expired reset code"),
+            PasswordError::TooManyAttempts(_) => f.write_str("Too many attempts, try again later"),
+            PasswordError::InternalError => f.write_str("Internal Error"),
+        }
+    }
+}
+
+impl Error for PasswordError {}
+
+impl From<PasswordError> for ApiError {
+    fn from(err: PasswordError) -> Self {
+        let code = match &err {
+            PasswordError::MissingDetails => ErrorCode::MissingDetails,
+            PasswordError::WrongPassword => ErrorCode::WrongPassword,
+            PasswordError::InvalidPassword(reason) => {
+                return ApiError::new(ErrorCode::InvalidPassword, &err).with_details(reason)
+            }
+            PasswordError::UnknownUser => ErrorCode::UserNotFound,
+            PasswordError::InvalidCode => ErrorCode::InvalidResetCode,
+            PasswordError::TooManyAttempts(retry_after) => {
+                return ApiError::new(ErrorCode::TooManyAttempts, &err)
+                    .with_retry_after(*retry_after)
+            }
+            PasswordError::InternalError => ErrorCode::InternalError,
+        };
+        ApiError::new(code, err)
+    }
+}
+
+impl IntoResponse for PasswordError {
+    fn into_response(self) -> axum::response::Response {
+        ApiError::from(self).into_response()
+    }
+}
+
+/// Rules new passwords must follow, at signup and when changing or resetting them.
+pub(crate) struct PasswordPolicy {
+    pub min_length: usize,
+    /// Bounds the cost of hashing, which grows with the password length.
+    pub max_length: usize,
+    /// Lowercase passwords refused whatever the case they are typed in.
+    pub rejected: HashSet<String>,
+}
+
+/// A short list of the most used passwords, extended with the `PASSWORD_REJECT_LIST` secret.
+const COMMON_PASSWORDS: &[&str] = &[
+    "123456",
+    "123456789",
+    "12345678",
+    "1234567890",
+    "12345",
+    "1234567",
+    "111111",
+    "000000",
+    "123123",
+    "654321",
+    "666666",
+    "121212",
+    "112233",
+    "987654321",
+    "password",
+    "password1",
+    "password123",
+    "passw0rd",
+    "qwerty",
+    "qwerty123",
+    "qwertyuiop",
+    "azerty",
+    "azertyuiop",
+    "abc123",
+    "abcd1234",
+    "1q2w3e4r",
+    "1qaz2wsx",
+    "zaq12wsx",
+    "iloveyou",
+    "admin",
+    "admin123",
+    "welcome",
+    "welcome1",
+    "letmein",
+    "monkey",
+    "dragon",
+    "football",
+    "baseball",
+    "soccer",
+    "princess",
+    "sunshine",
+    "superman",
+    "batman",
+    "starwars",
+    "pokemon",
+    "minecraft",
+    "master",
+    "shadow",
+    "trustno1",
+    "whatever",
+    "freedom",
+    "hello123",
+    "secret",
+    "changeme",
+    "computer",
+    "internet",
+    "gaming",
+    "gamer",
+    "champion",
+    "champions",
+    "points",
+];
+
+impl PasswordPolicy {
+    pub fn from_secrets(secrets: &SecretStore) -> Self {
+        let mut rejected: HashSet<String> = COMMON_PASSWORDS
+            .iter()
+            .map(|password| password.to_string())
+            .collect();
+        if let Some(list) = secrets.get("PASSWORD_REJECT_LIST") {
+            rejected.extend(
+                list.split(',')
+                    .map(|password| password.trim().to_lowercase())
+                    .filter(|password| !password.is_empty()),
+            );
+        }
+        Self {
+            min_length: config::get_or(secrets, "PASSWORD_MIN_LENGTH", 8),
+            max_length: config::get_or(secrets, "PASSWORD_MAX_LENGTH", 128),
+            rejected,
+        }
+    }
+
+    pub fn check(&self, name: &str, password: &str) -> Result<(), PolicyViolation> {
+        let length = password.chars().count();
+        if length < self.min_length {
+            return Err(PolicyViolation::TooShort {
+                min_length: self.min_length,
+            });
+        }
+        if length > self.max_length {
+            return Err(PolicyViolation::TooLong {
+                max_length: self.max_length,
+            });
+        }
+        let lowercase = password.to_lowercase();
+        if lowercase == name.to_lowercase() {
+            return Err(PolicyViolation::SameAsName);
+        }
+        if self.rejected.contains(&lowercase) {
+            return Err(PolicyViolation::TooCommon);
+        }
+        Ok(())
+    }
+}
+
+fn internal_error(err: sqlx::Error) -> PasswordError {
+    tracing::error!("Password update failed on a database error: {}", err);
+    PasswordError::InternalError
+}
+
+/// Settings for admin-issued password reset codes.
+pub(crate) struct ResetCodeConfig {
+    pub lifetime: Duration,
+}
+
+impl ResetCodeConfig {
+    pub fn from_secrets(secrets: &SecretStore) -> Self {
+        Self {
+            lifetime: config::get_secs(
+                secrets,
+                "RESET_CODE_LIFETIME_SECS",
+                Duration::from_secs(60 * 60),
+            ),
+        }
+    }
+}
+
+/// Changes the password of the current user, and logs out their other sessions.
+pub(crate) async fn post_change_password(
+    RequireUser(user, token): RequireUser,
+    Extension(database): Extension<PgPool>,
+    Extension(throttle): Extension<Arc<LoginThrottle>>,
+    Extension(password_policy): Extension<Arc<PasswordPolicy>>,
+    Extension(hashing): Extension<Arc<PasswordHashing>>,
+    Json(PasswordChangeData {
+        current_password,
+        new_password,
+    }): Json<PasswordChangeData>,
+) -> Result<impl IntoResponse, PasswordError> {
+    const PASSWORD_QUERY: &str = "SELECT password FROM users WHERE id = $1;";
+    const UPDATE_QUERY: &str = "UPDATE users SET password = $2 WHERE id = $1;";
+    const REVOKE_QUERY: &str = "DELETE FROM sessions WHERE user_id = $1 AND session_token <> $2;";
+
+    if current_password.is_empty() || new_password.is_empty() {
+        return Err(PasswordError::MissingDetails);
+    }
+    password_policy
+        .check(&user.name, &new_password)
+        .map_err(PasswordError::InvalidPassword)?;
+    // A stolen session must not be a way to guess the password without limits.
+    let keys = vec![AttemptKey::Account(user.name.clone())];
+    let attempt = throttle
+        .attempt(&database, keys)
+        .await
+        .map_err(internal_error)?
+        .map_err(PasswordError::TooManyAttempts)?;
+
+    let (correct_hashed_password,): (String,) = sqlx::query_as(PASSWORD_QUERY)
+        .bind(user.id)
+        .fetch_one(&database)
+        .await
+        .map_err(internal_error)?;
+    if hashing
+        .verify(&current_password, &correct_hashed_password)
+        .await
+        .is_err()
+    {
+        tracing::info!(name = %user.name, "Password change with a wrong current password");
+        return Err(PasswordError::WrongPassword);
+    }
+    throttle
+        .record_success(&database, attempt)
+        .await
+        .map_err(internal_error)?;
+
+    let hashed_password = hashing.hash(new_password).await;
+    let mut transaction = database.begin().await.map_err(internal_error)?;
+    sqlx::query(UPDATE_QUERY)
+        .bind(user.id)
+        .bind(hashed_password)
+        .execute(&mut *transaction)
+        .await
+        .map_err(internal_error)?;
+    sqlx::query(REVOKE_QUERY)
+        .bind(user.id)
+        .bind(token.into_database_value())
+        .execute(&mut *transaction)
+        .await
+        .map_err(internal_error)?;
+    transaction.commit().await.map_err(internal_error)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Issues a one-time reset code for a user who lost their password, replacing any unused one.
+///
+/// An admin hands the code over to the player, who redeems it with [`post_reset_password`].
+pub(crate) async fn post_reset_code(
+    RequireAdmin(admin): RequireAdmin,
+    Extension(random): Extension<Random>,
+    Extension(database): Extension<PgPool>,
+    Extension(config): Extension<Arc<ResetCodeConfig>>,
+    Extension(hashing): Extension<Arc<PasswordHashing>>,
+    Path(name): Path<String>,
+) -> Result<impl IntoResponse, PasswordError> {
+    const USER_QUERY: &str = "SELECT id FROM users WHERE name = $1;";
+    const DELETE_QUERY: &str =
+        "DELETE FROM password_reset_codes WHERE user_id = $1 AND used_at IS NULL;";
+    const INSERT_QUERY: &str =
+        "INSERT INTO password_reset_codes (user_id, code_hash, created_at, expires_at)
+        VALUES ($1, $2, $3, $4);";
+
+    let (user_id,): (i32,) = sqlx::query_as(USER_QUERY)
+        .bind(&name)
+        .fetch_optional(&database)
+        .await
+        .map_err(internal_error)?
+        .ok_or(PasswordError::UnknownUser)?;
+
+    let code = generate_code(&random);
+    let now = now_utc();
+    let expires_at = now + config.lifetime;
+    let mut transaction = database.begin().await.map_err(internal_error)?;
+    sqlx::query(DELETE_QUERY)
+        .bind(user_id)
+        .execute(&mut *transaction)
+        .await
+        .map_err(internal_error)?;
+    sqlx::query(INSERT_QUERY)
+        .bind(user_id)
+        .bind(hashing.hash(&code).await)
+        .bind(now)
+        .bind(expires_at)
+        .execute(&mut *transaction)
+        .await
+        .map_err(internal_error)?;
+    transaction.commit().await.map_err(internal_error)?;
+
+    tracing::info!(%name, by = %admin.name, "Issued a password reset code");
+    Ok((
+        StatusCode::OK,
+        Json(ResetCodeData {
+            code,
+            expires_at: expires_at.assume_utc(),
+        }),
+    ))
+}
+
+/// Sets a new password using a reset code, and logs out every session of the user.
+pub(crate) async fn post_reset_password(
+    Extension(database): Extension<PgPool>,
+    Extension(throttle): Extension<Arc<LoginThrottle>>,
+    Extension(password_policy): Extension<Arc<PasswordPolicy>>,
+    Extension(hashing): Extension<Arc<PasswordHashing>>,
+    headers: HeaderMap,
+    Json(PasswordResetData {
+        name,
+        code,
+        new_password,
+    }): Json<PasswordResetData>,
+) -> Result<impl IntoResponse, PasswordError> {
+    const CODES_QUERY: &str = "SELECT password_reset_codes.id, user_id, code_hash
+        FROM password_reset_codes JOIN users ON users.id = user_id
+        WHERE users.name = $1 AND used_at IS NULL AND expires_at > $2;";
+    const USE_QUERY: &str =
+        "UPDATE password_reset_codes SET used_at = $2 WHERE id = $1 AND used_at IS NULL;";
+    const UPDATE_QUERY: &str = "UPDATE users SET password = $2 WHERE id = $1;";
+    const REVOKE_QUERY: &str = "DELETE FROM sessions WHERE user_id = $1;";
+
+    if name.is_empty() || code.is_empty() || new_password.is_empty() {
+        return Err(PasswordError::MissingDetails);
+    }
+    password_policy
+        .check(&name, &new_password)
+        .map_err(PasswordError::InvalidPassword)?;
+    if name.chars().count() > MAX_NAME_LENGTH {
+        return Err(PasswordError::InvalidCode);
+    }
+    // Codes are short enough to be guessed, so they get the same limits as passwords.
+    let ip = client_ip(&headers);
+    let keys: Vec<AttemptKey> = [
+        Some(AttemptKey::Account(name.clone())),
+        ip.map(AttemptKey::Ip),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    let attempt = throttle
+        .attempt(&database, keys)
+        .await
+        .map_err(internal_error)?
+        .map_err(PasswordError::TooManyAttempts)?;
+
+    let now = now_utc();
+    let codes: Vec<(i32, i32, String)> = sqlx::query_as(CODES_QUERY)
+        .bind(&name)
+        .bind(now)
+        .fetch_all(&database)
+        .await
+        .map_err(internal_error)?;
+    let code = normalize_code(&code);
+    let mut matching = None;
+    for (code_id, user_id, code_hash) in codes {
+        if hashing.verify(&code, &code_hash).await.is_ok() {
+            matching = Some((code_id, user_id));
+            break;
+        }
+    }
+    let Some((code_id, user_id)) = matching else {
+        tracing::info!(%name, ?ip, "Password reset with an invalid code");
+        return Err(PasswordError::InvalidCode);
+    };
+
+    let mut transaction = database.begin().await.map_err(internal_error)?;
+    let used = sqlx::query(USE_QUERY)
+        .bind(code_id)
+        .bind(now)
+        .execute(&mut *transaction)
+        .await
+        .map_err(internal_error)?;
+    if used.rows_affected() == 0 {
+        // Redeemed concurrently by another request.
+        return Err(PasswordError::InvalidCode);
+    }
+    sqlx::query(UPDATE_QUERY)
+        .bind(user_id)
+        .bind(hashing.hash(new_password).await)
+        .execute(&mut *transaction)
+        .await
+        .map_err(internal_error)?;
+    sqlx::query(REVOKE_QUERY)
+        .bind(user_id)
+        .execute(&mut *transaction)
+        .await
+        .map_err(internal_error)?;
+    transaction.commit().await.map_err(internal_error)?;
+
+    throttle
+        .record_success(&database, attempt)
+        .await
+        .map_err(internal_error)?;
+    tracing::info!(%name, "Password reset with a code");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Characters of reset codes, without the ones easily mistaken for each other (0/O, 1/I/L).
+const CODE_ALPHABET: &[u8] = b"ABCDEFGHJKMNPQRSTUVWXYZ23456789";
+const CODE_LENGTH: usize = 12;
+
+fn generate_code(random: &Random) -> String {
+    let mut random = random.lock().unwrap();
+    (0..CODE_LENGTH)
+        .map(|_| CODE_ALPHABET[random.gen_range(0..CODE_ALPHABET.len())] as char)
+        .collect()
+}
+
+/// Codes are read out loud or copied by hand: ignore case, spaces and dashes.
+fn normalize_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| !matches!(c, ' ' | '-'))
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(min_length: usize, max_length: usize) -> PasswordPolicy {
+        PasswordPolicy {
+            min_length,
+            max_length,
+            rejected: HashSet::new(),
+        }
+    }
+
+    #[test]
+    fn policy_counts_characters_not_bytes() {
+        let policy = policy(8, 16);
+        assert!(matches!(
+            policy.check("alice", "ééééééé"),
+            Err(PolicyViolation::TooShort { min_length: 8 })
+        ));
+        assert!(policy.check("alice", "éééééééé").is_ok());
+        assert!(matches!(
+            policy.check("alice", "correct horse battery"),
+            Err(PolicyViolation::TooLong { max_length: 16 })
+        ));
+    }
+
+    #[test]
+    fn policy_ignores_case_of_name() {
+        assert!(matches!(
+            policy(8, 16).check("LongName1", "longname1"),
+            Err(PolicyViolation::SameAsName)
+        ));
+    }
+
+    #[test]
+    fn policy_ignores_case_of_rejected() {
+        let policy = PasswordPolicy {
+            rejected: ["password".to_string()].into(),
+            ..policy(8, 16)
+        };
+        assert!(matches!(
+            policy.check("alice", "PassWord"),
+            Err(PolicyViolation::TooCommon)
+        ));
+        assert!(policy.check("alice", "correct horse").is_ok());
+    }
+
+    #[test]
+    fn normalize_code_ignores_case_spaces_and_dashes() {
+        assert_eq!(normalize_code("abcd-efgh"), "ABCDEFGH");
+        assert_eq!(normalize_code(" ab cd - EF gh "), "ABCDEFGH");
+        assert_eq!(normalize_code("ABCDEFGH"), "ABCDEFGH");
+    }
+}
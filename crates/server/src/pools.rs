@@ -0,0 +1,263 @@
+use std::{sync::Arc, time::Duration};
+
+use axum::{http::StatusCode, response::IntoResponse, Extension};
+use protocol::{
+    feed::FeedEvent,
+    live::GameEvent,
+    points::{PoolSettings, PoolStatus, GLOBAL_POOL},
+};
+use sqlx::{PgConnection, PgPool};
+use time::PrimitiveDateTime;
+
+use crate::{
+    authentication::RequireAdmin,
+    economy::EconomyConfig,
+    error::{ApiError, ErrorCode},
+    extract::{Json, Path},
+    feed,
+    ledger::PointEvent,
+    live, now_utc, Random,
+};
+
+type PoolRow = (i32, String, i32, PrimitiveDateTime, Option<i32>);
+
+fn status_from_row(
+    (id, name, points, open_at, team_id): PoolRow,
+    now: PrimitiveDateTime,
+) -> PoolStatus {
+    PoolStatus {
+        id,
+        name,
+        points,
+        open: open_at < now && points > 0,
+        open_at: open_at.assume_utc(),
+        team_id,
+    }
+}
+
+/// State of every pool, so clients can show countdowns instead of polling collect.
+pub(crate) async fn get_pools(
+    Extension(database): Extension<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    let mut connection = database.acquire().await?;
+    Ok(Json(all_statuses(&mut connection).await?))
+}
+
+pub(crate) async fn all_statuses(
+    connection: &mut PgConnection,
+) -> Result<Vec<PoolStatus>, sqlx::Error> {
+    const QUERY: &str = "SELECT id, name, points, open_at, team_id FROM points_pool ORDER BY id;";
+
+    let now = now_utc();
+    let rows: Vec<PoolRow> = sqlx::query_as(QUERY).fetch_all(connection).await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| status_from_row(row, now))
+        .collect())
+}
+
+/// State of the pool, as seen by `connection`'s transaction.
+pub(crate) async fn status(
+    connection: &mut PgConnection,
+    pool_id: i32,
+) -> Result<PoolStatus, sqlx::Error> {
+    const QUERY: &str = "SELECT id, name, points, open_at, team_id FROM points_pool WHERE id = $1;";
+
+    let row: PoolRow = sqlx::query_as(QUERY)
+        .bind(pool_id)
+        .fetch_one(connection)
+        .await?;
+    Ok(status_from_row(row, now_utc()))
+}
+
+pub(crate) async fn get_pool(
+    Extension(database): Extension<PgPool>,
+    Path(name): Path<String>,
+) -> Result<impl IntoResponse, ApiError> {
+    pool_status(&database, &name).await
+}
+
+/// State of the global pool.
+pub(crate) async fn get_global_pool(
+    Extension(database): Extension<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    pool_status(&database, GLOBAL_POOL).await
+}
+
+async fn pool_status(database: &PgPool, name: &str) -> Result<Json<PoolStatus>, ApiError> {
+    const QUERY: &str =
+        "SELECT id, name, points, open_at, team_id FROM points_pool WHERE name = $1;";
+
+    let row: PoolRow = sqlx::query_as(QUERY)
+        .bind(name)
+        .fetch_optional(database)
+        .await?
+        .ok_or_else(pool_not_found)?;
+    Ok(Json(status_from_row(row, now_utc())))
+}
+
+pub(crate) fn pool_not_found() -> ApiError {
+    ApiError::new(ErrorCode::PoolNotFound, "No such pool.")
+}
+
+/// Creates a pool, open right away with a first refill, or changes the settings of one.
+pub(crate) async fn put_pool(
+    RequireAdmin(admin): RequireAdmin,
+    Extension(database): Extension<PgPool>,
+    Extension(economy): Extension<Arc<EconomyConfig>>,
+    Path(name): Path<String>,
+    Json(settings): Json<PoolSettings>,
+) -> Result<impl IntoResponse, ApiError> {
+    const PREVIOUS_QUERY: &str = "SELECT points FROM points_pool WHERE name = $1 FOR UPDATE;";
+    // A lower capacity takes the points above it out of the pool.
+    const UPSERT_QUERY: &str = "INSERT INTO points_pool (name, points, open_at, capacity,
+            refill_points, reopen_delay_min_secs, reopen_delay_max_secs, team_id)
+        VALUES ($1, LEAST($2, COALESCE($3, $2)), $8, $3, $4, $5, $6, $7)
+        ON CONFLICT (name) DO UPDATE SET capacity = $3, refill_points = $4,
+            reopen_delay_min_secs = $5, reopen_delay_max_secs = $6, team_id = $7,
+            points = LEAST(points_pool.points, COALESCE($3, points_pool.points))
+        RETURNING id, points, (xmax = 0) AS created;";
+
+    let valid_name = (1..=32).contains(&name.len())
+        && name
+            .chars()
+            .all(|c| matches!(c, 'a'..='z' | '0'..='9' | '-'));
+    if !valid_name {
+        return Err(ApiError::new(
+            ErrorCode::InvalidPoolSettings,
+            "Pool names are up to 32 lowercase letters, digits and dashes.",
+        ));
+    }
+    let positive = [settings.capacity, settings.refill_points]
+        .into_iter()
+        .flatten()
+        .all(|value| value > 0);
+    let delays = [
+        settings.reopen_delay_min_secs,
+        settings.reopen_delay_max_secs,
+    ];
+    let ordered = match delays {
+        [Some(min), Some(max)] => min <= max,
+        _ => true,
+    };
+    if !positive || delays.into_iter().flatten().any(|delay| delay < 0) || !ordered {
+        return Err(ApiError::new(
+            ErrorCode::InvalidPoolSettings,
+            "Amounts must be positive, and delays ordered and not negative.",
+        ));
+    }
+
+    let mut transaction = database.begin().await?;
+    let previous_points: Option<(i32,)> = sqlx::query_as(PREVIOUS_QUERY)
+        .bind(&name)
+        .fetch_optional(&mut *transaction)
+        .await?;
+    let upserted: Result<(i32, i32, bool), _> = sqlx::query_as(UPSERT_QUERY)
+        .bind(&name)
+        .bind(settings.refill_points.unwrap_or(economy.refill_points))
+        .bind(settings.capacity)
+        .bind(settings.refill_points)
+        .bind(settings.reopen_delay_min_secs)
+        .bind(settings.reopen_delay_max_secs)
+        .bind(settings.team_id)
+        .bind(now_utc())
+        .fetch_one(&mut *transaction)
+        .await;
+    let (pool_id, points, created) = match upserted {
+        Ok(row) => row,
+        Err(sqlx::Error::Database(e)) if e.constraint() == Some("points_pool_team_id_fkey") => {
+            return Err(ApiError::new(ErrorCode::TeamNotFound, "No such team."));
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let pool = status(&mut transaction, pool_id).await?;
+    live::publish(&mut transaction, &GameEvent::Pool(pool.clone())).await?;
+    if created {
+        PointEvent::Refill {
+            pool_id,
+            amount: points,
+        }
+        .record(&mut transaction)
+        .await?;
+        feed::record(
+            &mut transaction,
+            &FeedEvent::Refill {
+                amount: points,
+                pool,
+            },
+        )
+        .await?;
+    } else if let Some(amount) = previous_points
+        .map(|(previous,)| points - previous)
+        .filter(|amount| *amount != 0)
+    {
+        PointEvent::Adjust {
+            user_id: admin.id,
+            pool_id,
+            amount,
+        }
+        .record(&mut transaction)
+        .await?;
+        feed::record(&mut transaction, &FeedEvent::Adjust { amount, pool }).await?;
+    }
+    transaction.commit().await?;
+    tracing::info!(admin = %admin.name, %name, created, "Saved pool settings");
+    Ok(if created {
+        StatusCode::CREATED
+    } else {
+        StatusCode::NO_CONTENT
+    })
+}
+
+/// Puts points back in an empty pool, which reopens after a random delay.
+///
+/// Run by the scheduler; the pool row must be locked by the caller.
+pub(crate) async fn refill(
+    connection: &mut PgConnection,
+    economy: &EconomyConfig,
+    random: &Random,
+    pool_id: i32,
+) -> Result<(), sqlx::Error> {
+    const POOL_QUERY: &str = "SELECT points, refill_points, reopen_delay_min_secs,
+            reopen_delay_max_secs
+        FROM points_pool WHERE id = $1;";
+    const REFILL_QUERY: &str = "UPDATE points_pool
+        SET points = LEAST(points + $2, COALESCE(capacity, points + $2)), open_at = $3
+        WHERE id = $1
+        RETURNING points;";
+
+    let (points, refill_points, delay_min, delay_max): (
+        i32,
+        Option<i32>,
+        Option<i32>,
+        Option<i32>,
+    ) = sqlx::query_as(POOL_QUERY)
+        .bind(pool_id)
+        .fetch_one(&mut *connection)
+        .await?;
+    let delay_secs = |secs: Option<i32>| secs.map(|secs| Duration::from_secs(secs as u64));
+    let open_at =
+        now_utc() + economy.reopen_delay(random, delay_secs(delay_min), delay_secs(delay_max));
+    let (refilled,): (i32,) = sqlx::query_as(REFILL_QUERY)
+        .bind(pool_id)
+        .bind(refill_points.unwrap_or(economy.refill_points))
+        .bind(open_at)
+        .fetch_one(&mut *connection)
+        .await?;
+    PointEvent::Refill {
+        pool_id,
+        amount: refilled - points,
+    }
+    .record(&mut *connection)
+    .await?;
+    let pool = status(&mut *connection, pool_id).await?;
+    live::publish(&mut *connection, &GameEvent::Pool(pool.clone())).await?;
+    feed::record(
+        connection,
+        &FeedEvent::Refill {
+            amount: refilled - points,
+            pool,
+        },
+    )
+    .await
+}
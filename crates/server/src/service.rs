@@ -0,0 +1,59 @@
+//! The shuttle service: the axum server, and the background jobs living alongside it.
+
+use std::net::SocketAddr;
+
+use axum::Router;
+use shuttle_runtime::CustomError;
+
+use crate::jobs::Scheduler;
+
+/// Serves the router until the process is asked to stop, then stops the jobs and waits
+/// for their runs in progress.
+pub(crate) struct GameService {
+    pub router: Router,
+    pub scheduler: Scheduler,
+}
+
+#[shuttle_runtime::async_trait]
+impl shuttle_runtime::Service for GameService {
+    async fn bind(mut self, addr: SocketAddr) -> Result<(), shuttle_runtime::Error> {
+        let server = axum::Server::bind(&addr).serve(self.router.into_make_service());
+        // Not a graceful shutdown of the server: WebSockets and feeds never end on their
+        // own. Requests in progress are dropped, and their transactions roll back.
+        tokio::select! {
+            result = server => result.map_err(CustomError::new)?,
+            () = shutdown_signal() => tracing::info!("Shutting down"),
+        }
+        self.scheduler.stop().await;
+        Ok(())
+    }
+}
+
+/// Resolves on Ctrl+C, or SIGTERM where there is one.
+async fn shutdown_signal() {
+    let interrupt = async {
+        if let Err(err) = tokio::signal::ctrl_c().await {
+            tracing::error!("Can't listen for Ctrl+C: {}", err);
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(err) => {
+                tracing::error!("Can't listen for SIGTERM: {}", err);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        () = interrupt => {}
+        () = terminate => {}
+    }
+}
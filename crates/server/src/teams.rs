@@ -0,0 +1,121 @@
+use std::{sync::Arc, time::Duration};
+
+use axum::{http::StatusCode, response::IntoResponse, Extension};
+use protocol::teams::Membership;
+use shuttle_secrets::SecretStore;
+use sqlx::PgPool;
+use time::{format_description::well_known::Rfc3339, PrimitiveDateTime};
+
+use crate::{
+    authentication::RequireUser,
+    config,
+    error::{ApiError, ErrorCode},
+    extract::{Json, Path},
+    now_utc,
+};
+
+pub(crate) struct TeamConfig {
+    /// Time to wait after joining or leaving a team before changing again.
+    /// The very first join is always allowed.
+    pub switch_cooldown: Duration,
+    /// Only let players assign points to the champions of their own team.
+    pub assign_own_team_only: bool,
+}
+
+impl TeamConfig {
+    pub fn from_secrets(secrets: &SecretStore) -> Self {
+        Self {
+            switch_cooldown: config::get_secs(
+                secrets,
+                "TEAM_SWITCH_COOLDOWN_SECS",
+                Duration::from_secs(24 * 60 * 60),
+            ),
+            assign_own_team_only: config::get_or(secrets, "TEAM_ASSIGN_OWN_ONLY", false),
+        }
+    }
+}
+
+/// Joins the team, leaving the current one if any.
+pub(crate) async fn post_join_team(
+    RequireUser(user, _): RequireUser,
+    Extension(database): Extension<PgPool>,
+    Extension(config): Extension<Arc<TeamConfig>>,
+    Path(team_id): Path<i32>,
+) -> Result<impl IntoResponse, ApiError> {
+    const TEAM_QUERY: &str = "SELECT 1 FROM teams WHERE id = $1;";
+
+    sqlx::query(TEAM_QUERY)
+        .bind(team_id)
+        .fetch_optional(&database)
+        .await?
+        .ok_or_else(|| ApiError::new(ErrorCode::TeamNotFound, "No such team."))?;
+    change_team(&database, &config, user.id, Some(team_id)).await
+}
+
+/// Leaves the current team, which also starts the cooldown.
+pub(crate) async fn post_leave_team(
+    RequireUser(user, _): RequireUser,
+    Extension(database): Extension<PgPool>,
+    Extension(config): Extension<Arc<TeamConfig>>,
+) -> Result<impl IntoResponse, ApiError> {
+    change_team(&database, &config, user.id, None).await
+}
+
+async fn change_team(
+    database: &PgPool,
+    config: &TeamConfig,
+    user_id: i32,
+    team_id: Option<i32>,
+) -> Result<(StatusCode, Json<Membership>), ApiError> {
+    const USER_QUERY: &str = "SELECT team_id, team_changed_at FROM users WHERE id = $1 FOR UPDATE;";
+    const UPDATE_QUERY: &str = "UPDATE users SET team_id = $2, team_changed_at = $3 WHERE id = $1;";
+
+    let now = now_utc();
+    let mut transaction = database.begin().await?;
+    let (current_team_id, changed_at): (Option<i32>, Option<PrimitiveDateTime>) =
+        sqlx::query_as(USER_QUERY)
+            .bind(user_id)
+            .fetch_one(&mut *transaction)
+            .await?;
+    let next_change_at = changed_at.map(|changed_at| changed_at + config.switch_cooldown);
+
+    if current_team_id == team_id {
+        return Ok((
+            StatusCode::OK,
+            Json(Membership {
+                team_id,
+                next_change_at: next_change_at.unwrap_or(now).assume_utc(),
+            }),
+        ));
+    }
+    if let Some(next_change_at) = next_change_at.filter(|next_change_at| *next_change_at > now) {
+        let next_change_at = next_change_at.assume_utc();
+        return Err(
+            ApiError::new(ErrorCode::TeamCooldown, "You changed team too recently.")
+                .with_details(serde_json::json!({
+                    "next_change_at": next_change_at.format(&Rfc3339).ok(),
+                }))
+                .with_retry_after(
+                    (next_change_at - now.assume_utc())
+                        .try_into()
+                        .unwrap_or_default(),
+                ),
+        );
+    }
+
+    sqlx::query(UPDATE_QUERY)
+        .bind(user_id)
+        .bind(team_id)
+        .bind(now)
+        .execute(&mut *transaction)
+        .await?;
+    transaction.commit().await?;
+    tracing::info!(user_id, ?current_team_id, ?team_id, "Changed team");
+    Ok((
+        StatusCode::OK,
+        Json(Membership {
+            team_id,
+            next_change_at: (now + config.switch_cooldown).assume_utc(),
+        }),
+    ))
+}
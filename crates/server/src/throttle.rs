@@ -0,0 +1,302 @@
+use std::{net::IpAddr, time::Duration};
+
+use shuttle_secrets::SecretStore;
+use sqlx::{PgConnection, PgPool};
+use time::PrimitiveDateTime;
+
+use crate::{config, now_utc};
+
+/// Length limit of `users.name`, in characters.
+pub(crate) const MAX_NAME_LENGTH: usize = 32;
+
+/// Slows down password guessing, by tracking failed logins per account and per IP.
+///
+/// After a few free attempts, each failure blocks the key for an exponentially growing
+/// delay, and enough failures lock it out for a while. Names longer than any account
+/// are refused before reaching it, see [`MAX_NAME_LENGTH`]. Attempts are stored in the
+/// database so every server instance sees the same counters.
+pub(crate) struct LoginThrottle {
+    /// Failures allowed for an account name before delays kick in.
+    pub account_free_attempts: u32,
+    /// Same for an IP; higher since players at an event share the venue's address.
+    pub ip_free_attempts: u32,
+    /// Failures (past the free ones) after which the key is locked out.
+    pub lockout_after: u32,
+    pub base_delay: Duration,
+    pub lockout: Duration,
+    /// Failures older than this are forgotten.
+    pub forget_after: Duration,
+}
+
+/// A login attempt that was let through, counted as a failure until it succeeds.
+pub(crate) struct Attempt {
+    keys: Vec<AttemptKey>,
+    at: PrimitiveDateTime,
+}
+
+/// What a login attempt is counted against.
+pub(crate) enum AttemptKey {
+    Account(String),
+    Ip(IpAddr),
+}
+
+impl AttemptKey {
+    fn database_value(&self) -> String {
+        match self {
+            AttemptKey::Account(name) => format!("account:{}", name),
+            AttemptKey::Ip(ip) => format!("ip:{}", ip),
+        }
+    }
+}
+
+impl LoginThrottle {
+    pub fn from_secrets(secrets: &SecretStore) -> Self {
+        Self {
+            account_free_attempts: config::get_or(secrets, "LOGIN_ACCOUNT_FREE_ATTEMPTS", 5),
+            ip_free_attempts: config::get_or(secrets, "LOGIN_IP_FREE_ATTEMPTS", 50),
+            lockout_after: config::get_or(secrets, "LOGIN_LOCKOUT_AFTER", 10),
+            base_delay: config::get_secs(secrets, "LOGIN_BASE_DELAY_SECS", Duration::from_secs(1)),
+            lockout: config::get_secs(secrets, "LOGIN_LOCKOUT_SECS", Duration::from_secs(15 * 60)),
+            forget_after: config::get_secs(
+                secrets,
+                "LOGIN_FORGET_AFTER_SECS",
+                Duration::from_secs(60 * 60),
+            ),
+        }
+    }
+
+    /// Counts an attempt against each of `keys`, unless one of them is blocked.
+    ///
+    /// The attempt counts as a failure until [`Self::record_success`], so parallel guesses
+    /// can't all get past the check before the first of them fails: counting, checking and
+    /// blocking the next attempt is one statement per key. When refused, returns how long
+    /// to wait; the keys checked before the blocked one still count the attempt.
+    pub async fn attempt(
+        &self,
+        database: &PgPool,
+        keys: Vec<AttemptKey>,
+    ) -> Result<Result<Attempt, Duration>, sqlx::Error> {
+        // `$4` holds the delay after each failure count, the last one applying past its end.
+        const ATTEMPT_QUERY: &str =
+            "INSERT INTO login_attempts AS attempts (key, failures, last_failure_at, blocked_until)
+            VALUES ($1, 1, $2, $2 + make_interval(secs => $4[1]))
+            ON CONFLICT (key) DO UPDATE SET
+                failures = CASE WHEN attempts.last_failure_at < $3 THEN 1
+                    ELSE attempts.failures + 1 END,
+                last_failure_at = $2,
+                blocked_until = $2 + make_interval(secs => $4[LEAST(
+                    CASE WHEN attempts.last_failure_at < $3 THEN 1
+                        ELSE attempts.failures + 1 END,
+                    cardinality($4))])
+            WHERE attempts.blocked_until <= $2
+            RETURNING failures;";
+        const BLOCKED_QUERY: &str = "SELECT blocked_until FROM login_attempts WHERE key = $1;";
+
+        let now = now_utc();
+        for key in &keys {
+            let counted: Option<(i32,)> = sqlx::query_as(ATTEMPT_QUERY)
+                .bind(key.database_value())
+                .bind(now)
+                .bind(now - self.forget_after)
+                .bind(self.delays(key))
+                .fetch_optional(database)
+                .await?;
+            if counted.is_none() {
+                let (blocked_until,): (PrimitiveDateTime,) = sqlx::query_as(BLOCKED_QUERY)
+                    .bind(key.database_value())
+                    .fetch_one(database)
+                    .await?;
+                return Ok(Err((blocked_until - now).try_into().unwrap_or_default()));
+            }
+        }
+        Ok(Ok(Attempt { keys, at: now }))
+    }
+
+    /// Takes back a successful `attempt`: forgets the past failures of its account, and
+    /// no longer counts it against the other keys.
+    pub async fn record_success(
+        &self,
+        database: &PgPool,
+        attempt: Attempt,
+    ) -> Result<(), sqlx::Error> {
+        const FORGET_QUERY: &str = "DELETE FROM login_attempts WHERE key = $1;";
+        // The block is only this attempt's if nothing was attempted since.
+        const UNDO_QUERY: &str = "UPDATE login_attempts SET failures = failures - 1,
+                blocked_until = CASE WHEN last_failure_at = $2 THEN $2 ELSE blocked_until END
+            WHERE key = $1 AND failures > 0;";
+
+        for key in &attempt.keys {
+            let query = match key {
+                AttemptKey::Account(_) => FORGET_QUERY,
+                AttemptKey::Ip(_) => UNDO_QUERY,
+            };
+            sqlx::query(query)
+                .bind(key.database_value())
+                .bind(attempt.at)
+                .execute(database)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Deletes the keys whose failures are forgotten and that are no longer blocked,
+    /// returning how many.
+    pub async fn prune(&self, connection: &mut PgConnection) -> Result<u64, sqlx::Error> {
+        const QUERY: &str =
+            "DELETE FROM login_attempts WHERE last_failure_at < $1 AND blocked_until <= $2;";
+
+        let now = now_utc();
+        let result = sqlx::query(QUERY)
+            .bind(now - self.forget_after)
+            .bind(now)
+            .execute(connection)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Delay after each number of failures of `key`, starting at one, up to the lockout.
+    fn delays(&self, key: &AttemptKey) -> Vec<f64> {
+        (1..=self.free_attempts(key) + self.lockout_after.max(1))
+            .map(|failures| {
+                self.delay_after(key, failures)
+                    .unwrap_or_default()
+                    .as_secs_f64()
+            })
+            .collect()
+    }
+
+    fn free_attempts(&self, key: &AttemptKey) -> u32 {
+        match key {
+            AttemptKey::Account(_) => self.account_free_attempts,
+            AttemptKey::Ip(_) => self.ip_free_attempts,
+        }
+    }
+
+    fn delay_after(&self, key: &AttemptKey, failures: u32) -> Option<Duration> {
+        let excess = failures
+            .checked_sub(self.free_attempts(key))
+            .filter(|excess| *excess > 0)?;
+        if excess >= self.lockout_after {
+            return Some(self.lockout);
+        }
+        let delay = self.base_delay.saturating_mul(1 << (excess - 1).min(31));
+        Some(delay.min(self.lockout))
+    }
+}
+
+/// Address of the client, as reported by the proxy in front of us.
+///
+/// The right-most `X-Forwarded-For` entry is the one our proxy added; the ones before it
+/// come from the client and can't be trusted.
+pub(crate) fn client_ip(headers: &http::HeaderMap) -> Option<IpAddr> {
+    headers
+        .get_all("X-Forwarded-For")
+        .iter()
+        .filter_map(|header| header.to_str().ok())
+        .flat_map(|header| header.split(','))
+        .last()
+        .or_else(|| {
+            headers
+                .get("X-Real-IP")
+                .and_then(|header| header.to_str().ok())
+        })
+        .and_then(|ip| ip.trim().parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn throttle(lockout_after: u32) -> LoginThrottle {
+        LoginThrottle {
+            account_free_attempts: 3,
+            ip_free_attempts: 10,
+            lockout_after,
+            base_delay: Duration::from_secs(1),
+            lockout: Duration::from_secs(60),
+            forget_after: Duration::from_secs(3600),
+        }
+    }
+
+    #[test]
+    fn delay_doubles_after_free_attempts_until_lockout() {
+        let account = AttemptKey::Account("alice".to_string());
+        let delays: Vec<_> = (1..=9)
+            .map(|failures| throttle(5).delay_after(&account, failures))
+            .collect();
+        let secs = |secs| Some(Duration::from_secs(secs));
+        assert_eq!(
+            delays,
+            [
+                None,
+                None,
+                None,
+                secs(1),
+                secs(2),
+                secs(4),
+                secs(8),
+                secs(60),
+                secs(60)
+            ]
+        );
+    }
+
+    #[test]
+    fn delay_is_capped_by_lockout() {
+        let account = AttemptKey::Account("alice".to_string());
+        assert_eq!(
+            throttle(100).delay_after(&account, 90),
+            Some(Duration::from_secs(60))
+        );
+    }
+
+    #[test]
+    fn ip_has_its_own_free_attempts() {
+        let ip = AttemptKey::Ip("10.0.0.1".parse().unwrap());
+        assert_eq!(throttle(5).delay_after(&ip, 10), None);
+        assert_eq!(
+            throttle(5).delay_after(&ip, 11),
+            Some(Duration::from_secs(1))
+        );
+    }
+
+    #[test]
+    fn delays_end_with_lockout() {
+        let delays = throttle(5).delays(&AttemptKey::Account("alice".to_string()));
+        assert_eq!(delays, [0.0, 0.0, 0.0, 1.0, 2.0, 4.0, 8.0, 60.0]);
+    }
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> http::HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| {
+                (
+                    http::HeaderName::from_static(name),
+                    http::HeaderValue::from_static(value),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn client_ip_trusts_only_last_forwarded_for() {
+        let headers = headers(&[
+            ("x-forwarded-for", "1.1.1.1, 2.2.2.2"),
+            ("x-forwarded-for", "3.3.3.3"),
+            ("x-real-ip", "4.4.4.4"),
+        ]);
+        assert_eq!(client_ip(&headers), "3.3.3.3".parse().ok());
+    }
+
+    #[test]
+    fn client_ip_falls_back_to_real_ip() {
+        let headers = headers(&[("x-real-ip", " ::1 ")]);
+        assert_eq!(client_ip(&headers), "::1".parse().ok());
+    }
+
+    #[test]
+    fn client_ip_ignores_invalid_addresses() {
+        assert_eq!(client_ip(&headers(&[("x-forwarded-for", "unknown")])), None);
+        assert_eq!(client_ip(&headers(&[])), None);
+    }
+}
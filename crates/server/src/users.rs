@@ -0,0 +1,56 @@
+use axum::{response::IntoResponse, Extension};
+use protocol::users::{ChampionContribution, Profile, TeamInfo};
+use sqlx::PgPool;
+use time::PrimitiveDateTime;
+
+use crate::{authentication::RequireUser, error::ApiError, extract::Json, now_utc};
+
+/// Profile of the logged in user.
+pub(crate) async fn get_me(
+    RequireUser(user, _): RequireUser,
+    Extension(database): Extension<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    const USER_QUERY: &str = "SELECT users.points, can_get_points_time, teams.id, teams.name
+        FROM users LEFT JOIN teams ON teams.id = users.team_id
+        WHERE users.id = $1;";
+    const CONTRIBUTIONS_QUERY: &str = "SELECT champion_id, champions.name, contributions.points
+        FROM champion_contributions contributions
+        JOIN champions ON champions.id = champion_id
+        WHERE user_id = $1 AND contributions.points > 0
+        ORDER BY contributions.points DESC, champions.name;";
+
+    let (points, can_get_points_time, team_id, team_name): (
+        i32,
+        PrimitiveDateTime,
+        Option<i32>,
+        Option<String>,
+    ) = sqlx::query_as(USER_QUERY)
+        .bind(user.id)
+        .fetch_one(&database)
+        .await?;
+    let champions: Vec<ChampionContribution> = sqlx::query_as(CONTRIBUTIONS_QUERY)
+        .bind(user.id)
+        .fetch_all(&database)
+        .await?
+        .into_iter()
+        .map(|(champion_id, name, points)| ChampionContribution {
+            champion_id,
+            name,
+            points,
+        })
+        .collect();
+
+    Ok(Json(Profile {
+        id: user.id,
+        name: user.name,
+        role: user.role,
+        points,
+        team: team_id
+            .zip(team_name)
+            .map(|(id, name)| TeamInfo { id, name }),
+        next_collect_at: can_get_points_time.assume_utc(),
+        can_collect: can_get_points_time <= now_utc(),
+        total_assigned: champions.iter().map(|c| i64::from(c.points)).sum(),
+        champions,
+    }))
+}